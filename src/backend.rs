@@ -0,0 +1,111 @@
+/*
+// Payment backends: everything the contract needs from the asset it holds. The
+// business logic in lib.rs only reaches the asset through `backend`, so it runs
+// the same against a token contract or, with the `mock-backend` feature, against
+// balances kept in this contract's own storage, which lets it be exercised
+// without deploying a token. Future backends (payment channels, custodial
+// ledgers) implement the same trait.
+*/
+use crate::{token, TokenInfo};
+use soroban_auth::{Identifier, Signature};
+use soroban_sdk::{BytesN, Env};
+
+pub(crate) trait PaymentBackend {
+    fn info(&self, env: &Env) -> TokenInfo;
+    fn balance(&self, env: &Env, id: &Identifier) -> i128;
+    fn allowance(&self, env: &Env, from: &Identifier, spender: &Identifier) -> i128;
+    // Moves `amount` from `from` into this contract, drawing on its allowance
+    fn pull(&self, env: &Env, from: &Identifier, amount: &i128);
+    // Pays `amount` out of this contract to `to`
+    fn push(&self, env: &Env, to: &Identifier, amount: &i128);
+}
+
+// Token contracts implementing the token interface, e.g. the Stellar asset contract
+pub(crate) struct TokenBackend {
+    pub token: BytesN<32>,
+}
+
+impl PaymentBackend for TokenBackend {
+    fn info(&self, env: &Env) -> TokenInfo {
+        let client = token::Client::new(env, &self.token);
+        TokenInfo {
+            decimals: client.decimals(),
+            symbol: client.symbol(),
+        }
+    }
+
+    fn balance(&self, env: &Env, id: &Identifier) -> i128 {
+        token::Client::new(env, &self.token).balance(id)
+    }
+
+    fn allowance(&self, env: &Env, from: &Identifier, spender: &Identifier) -> i128 {
+        token::Client::new(env, &self.token).allowance(from, spender)
+    }
+
+    /*
+    // @soroban tips: client.xfer_from()
+    // xfer
+    // - an unprivileged mutator, which changes the state of the contract but do not require special privileges
+    // - a "sender" can use xfer to send money to a "admin" or contract id. For xfer, the sender must provide authorization
+    // invoker auth (&Signature::Invoker) is enough to use the built-in token with classic accounts
+    // more info on: https://soroban.stellar.org/docs/built-in-contracts/stellar-asset-contract#sac-operations &
+    // https://soroban.stellar.org/docs/common-interfaces/token
+    */
+    fn pull(&self, env: &Env, from: &Identifier, amount: &i128) {
+        let to = Identifier::Contract(env.get_current_contract());
+        token::Client::new(env, &self.token).xfer_from(&Signature::Invoker, &0, from, &to, amount);
+    }
+
+    fn push(&self, env: &Env, to: &Identifier, amount: &i128) {
+        token::Client::new(env, &self.token).xfer(&Signature::Invoker, &0, to, amount);
+    }
+}
+
+/*
+// Balances kept in this contract's storage under ("mock", id), with unlimited
+// allowances. Only meant for testing the business logic without a token: `mint`
+// funds addresses, and pull/push move balances without any auth.
+*/
+#[cfg(feature = "mock-backend")]
+pub(crate) struct MockBackend;
+
+#[cfg(feature = "mock-backend")]
+impl MockBackend {
+    pub fn mint(env: &Env, to: &Identifier, amount: i128) {
+        let balance = MockBackend.balance(env, to);
+        env.storage().set((soroban_sdk::symbol!("mock"), to.clone()), balance + amount);
+    }
+}
+
+#[cfg(feature = "mock-backend")]
+impl PaymentBackend for MockBackend {
+    fn info(&self, env: &Env) -> TokenInfo {
+        TokenInfo {
+            decimals: 7,
+            symbol: soroban_sdk::Bytes::from_slice(env, b"MOCK"),
+        }
+    }
+
+    fn balance(&self, env: &Env, id: &Identifier) -> i128 {
+        env.storage()
+            .get((soroban_sdk::symbol!("mock"), id.clone()))
+            .unwrap_or(Ok(0))
+            .unwrap()
+    }
+
+    fn allowance(&self, _env: &Env, _from: &Identifier, _spender: &Identifier) -> i128 {
+        i128::MAX
+    }
+
+    fn pull(&self, env: &Env, from: &Identifier, amount: &i128) {
+        let to = Identifier::Contract(env.get_current_contract());
+        MockBackend::mint(env, from, -amount);
+        MockBackend::mint(env, &to, *amount);
+    }
+
+    fn push(&self, env: &Env, to: &Identifier, amount: &i128) {
+        let from = Identifier::Contract(env.get_current_contract());
+        MockBackend::mint(env, &from, -amount);
+        MockBackend::mint(env, to, *amount);
+    }
+}
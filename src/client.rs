@@ -0,0 +1,88 @@
+#![cfg(feature = "client")]
+
+/*
+// Off-chain bindings for services integrating with this contract (payment
+// backends, indexers). Values go through a local host Env, so invoke arguments
+// and event payloads are encoded exactly like the contract's own client does and
+// nobody has to hand-roll ScVal conversions.
+*/
+extern crate std;
+
+use crate::{Error, EVENTS_SCHEMA};
+use soroban_sdk::xdr::{ContractEvent, ContractEventBody, HostFunction, ScStatus, ScVal, ScVec};
+use soroban_sdk::{BytesN, Env, IntoVal, RawVal, Status, Symbol, TryFromVal, TryIntoVal, Vec};
+use std::vec::Vec as StdVec;
+
+/*
+// Builds the host function of a transaction invoking `function` on `contract`,
+// e.g. invoke(&env, &id, symbol!("deposit"), (token, amount, depositers, ...)).
+// It goes into an InvokeHostFunction operation whose footprint comes from
+// simulating the transaction.
+*/
+pub fn invoke<A: IntoVal<Env, Vec<RawVal>>>(
+    env: &Env,
+    contract: &BytesN<32>,
+    function: Symbol,
+    args: A,
+) -> HostFunction {
+    let mut call: StdVec<ScVal> = StdVec::new();
+    call.push(contract.try_into().unwrap());
+    call.push(function.try_into().unwrap());
+    for arg in args.into_val(env).iter() {
+        call.push(ScVal::try_from_val(env, arg.unwrap()).unwrap());
+    }
+    HostFunction::InvokeContract(ScVec::try_from(call).unwrap())
+}
+
+// One of this contract's events, with its topics and data loaded into the local Env
+pub struct Event {
+    pub name: Symbol,
+    // The topics after the schema version and the name, e.g. the attendee
+    pub topics: StdVec<RawVal>,
+    pub data: RawVal,
+}
+
+impl Event {
+    pub fn topic<T: TryFromVal<Env, RawVal>>(&self, env: &Env, index: usize) -> Option<T> {
+        T::try_from_val(env, *self.topics.get(index)?).ok()
+    }
+
+    pub fn data<T: TryFromVal<Env, RawVal>>(&self, env: &Env) -> Option<T> {
+        T::try_from_val(env, self.data).ok()
+    }
+}
+
+/*
+// Decodes an event emitted by `contract` under the current events schema. Events
+// from other contracts or schema versions are None, so a service can feed every
+// event of a transaction through this.
+*/
+pub fn decode_event(env: &Env, contract: &BytesN<32>, event: &ContractEvent) -> Option<Event> {
+    if event.contract_id.as_ref().map(|id| id.0) != Some(contract.to_array()) {
+        return None;
+    }
+    let ContractEventBody::V0(body) = &event.body;
+    let topics: StdVec<RawVal> = body
+        .topics
+        .iter()
+        .map(|topic| topic.try_into_val(env).ok())
+        .collect::<Option<_>>()?;
+    let schema: Symbol = Symbol::try_from_val(env, *topics.first()?).ok()?;
+    if schema != EVENTS_SCHEMA {
+        return None;
+    }
+    Some(Event {
+        name: Symbol::try_from_val(env, *topics.get(1)?).ok()?,
+        topics: topics[2..].to_vec(),
+        data: (&body.data).try_into_val(env).ok()?,
+    })
+}
+
+// Maps the status of a failed invocation to the contract's Error. Host failures
+// and plain panics (validation messages) are None.
+pub fn contract_error(status: &ScStatus) -> Option<Error> {
+    match status {
+        ScStatus::ContractError(code) => Error::try_from(Status::from_contract_error(*code)).ok(),
+        _ => None,
+    }
+}
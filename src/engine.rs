@@ -0,0 +1,196 @@
+/*
+// Distribution engine: the share math, accounting rules and state machine of the
+// contract as plain functions over integers. Nothing in here touches `Env`, so it
+// can be unit tested off-chain and reused by other Soroban contracts; the
+// contract in lib.rs only loads state, calls into this module and stores results.
+*/
+
+// Basis points in a whole
+pub const BPS: u32 = 10000;
+
+// Fixed-point precision of the TimeWeighted share factor
+pub const WEIGHT_SCALE: i128 = 1_000_000;
+
+/*
+// Phase of a meetup as seen by the state machine
+// - Registration: before the meetup date, deposits are open
+// - AwaitingSettlement: the meetup happened, check-ins are open, pool not settled
+// - Settled: the pool was settled by the first claim and claims are being paid
+// - Cancelled: registration closed below the minimum pool, deposits are refunded
+// - Locked: the share table is frozen and payouts can be executed in batches
+// - Emergency: depositors voted to withdraw pro-rata, the normal flow is over
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    Registration,
+    AwaitingSettlement,
+    Settled,
+    Cancelled,
+    Locked,
+    Emergency,
+}
+
+pub fn phase(
+    now: u64,
+    meetup_date: u64,
+    settled: bool,
+    locked: bool,
+    emergency: bool,
+    below_min_pool: bool,
+) -> Phase {
+    if emergency {
+        Phase::Emergency
+    } else if locked {
+        Phase::Locked
+    } else if settled {
+        Phase::Settled
+    } else if now <= meetup_date {
+        Phase::Registration
+    } else if below_min_pool {
+        Phase::Cancelled
+    } else {
+        Phase::AwaitingSettlement
+    }
+}
+
+// `bps` basis points of `amount`, rounded down
+pub fn bps_of(amount: i128, bps: u32) -> i128 {
+    amount * bps as i128 / BPS as i128
+}
+
+pub fn is_valid_bps(bps: u32) -> bool {
+    bps <= BPS
+}
+
+// Whether `amount` is within `slippage_bps` of `quoted`, either way
+pub fn within_slippage(amount: i128, quoted: i128, slippage_bps: u32) -> bool {
+    let band = bps_of(quoted, slippage_bps);
+    amount >= quoted - band && amount <= quoted + band
+}
+
+// Co-host splits have to cover the whole fee exactly
+pub fn splits_are_complete(total_bps: u32) -> bool {
+    total_bps == BPS
+}
+
+/*
+// A registration's weight is its deposit. In TimeWeighted mode it's scaled by how
+// early it was made: linearly from 2x at contract start down to 1x at the
+// deadline. Deposits and timestamps are far below the bounds where the i128
+// products could overflow.
+*/
+pub fn time_weight(amount: i128, registered_at: u64, started: u64, deadline: u64) -> i128 {
+    let mut factor = WEIGHT_SCALE;
+    if registered_at < deadline && started < deadline {
+        let early = (deadline - registered_at.max(started)) as i128;
+        factor += WEIGHT_SCALE * early / (deadline - started) as i128;
+    }
+    amount * factor / WEIGHT_SCALE
+}
+
+// An attendee's slice of `payout`, given their weight out of `total_weight`
+pub fn share(payout: i128, weight: i128, total_weight: i128) -> i128 {
+    if total_weight == 0 {
+        return 0;
+    }
+    payout * weight / total_weight
+}
+
+// Splits what's tracked into (profit pool, reserve left). The reserve is held out
+// of the pool and absorbs any shortfall first, the pool only what's beyond it
+pub fn pool_after_reserve(tracked: i128, reserve: i128, held: i128) -> (i128, i128) {
+    let reserve = reserve.min(tracked).max(0);
+    let shortfall = (tracked - held).max(0);
+    let covered = shortfall.min(reserve);
+    (tracked - reserve - (shortfall - covered), reserve - covered)
+}
+
+pub fn emergency_quorum_reached(votes: i128, total_deposited: i128, quorum_bps: u32) -> bool {
+    votes * BPS as i128 >= total_deposited * quorum_bps as i128
+}
+
+// A depositor's pro-rata part of the funds snapshotted by an emergency withdrawal
+pub fn emergency_share(pool: i128, deposit: i128, total_deposited: i128) -> i128 {
+    share(pool, deposit, total_deposited)
+}
+
+// Which of `cohorts` buckets an address hashing to `hash_prefix` falls into
+pub fn cohort(hash_prefix: u32, cohorts: u32) -> u32 {
+    hash_prefix % cohorts
+}
+
+pub fn claim_opens_at(meetup_date: u64, cohort: u32, spacing: u64) -> u64 {
+    meetup_date + cohort as u64 * spacing
+}
+
+/*
+// A piecewise-linear schedule evaluated at `at`: the bps of the (at, bps)
+// breakpoints, interpolated linearly between the two around `at` and flat
+// before the first and after the last one. An empty schedule is a flat 10000.
+*/
+pub fn schedule_bps<I: IntoIterator<Item = (u64, u32)>>(points: I, at: u64) -> u32 {
+    let mut previous: Option<(u64, u32)> = None;
+    for (x, bps) in points {
+        if at <= x {
+            return match previous {
+                Some((x0, bps0)) => {
+                    let delta = (bps as i128 - bps0 as i128) * (at - x0) as i128;
+                    (bps0 as i128 + delta / (x - x0) as i128) as u32
+                }
+                None => bps,
+            };
+        }
+        previous = Some((x, bps));
+    }
+    match previous {
+        Some((_, bps)) => bps,
+        None => BPS,
+    }
+}
+
+// At least one breakpoint, strictly increasing, none above 10000 bps
+pub fn is_valid_schedule<I: IntoIterator<Item = (u64, u32)>>(points: I) -> bool {
+    let mut previous: Option<u64> = None;
+    for (x, bps) in points {
+        if !is_valid_bps(bps) || previous.map_or(false, |x0| x <= x0) {
+            return false;
+        }
+        previous = Some(x);
+    }
+    previous.is_some()
+}
+
+// Attendee ratings of an event go from MIN_RATING to MAX_RATING
+pub const MIN_RATING: u32 = 1;
+pub const MAX_RATING: u32 = 5;
+
+// The average of `count` ratings summing to `sum`, in hundredths and rounded down
+pub fn average_rating(sum: u32, count: u32) -> u64 {
+    if count == 0 {
+        return 0;
+    }
+    sum as u64 * 100 / count as u64
+}
+
+// Integer square root, rounded down (Newton's method from above)
+pub fn isqrt(n: i128) -> i128 {
+    if n < 2 {
+        return n;
+    }
+    let mut x = n;
+    let mut y = n / 2 + n % 2;
+    while y < x {
+        x = y;
+        y = (x + n / x) / 2;
+    }
+    x
+}
+
+/*
+// A project's slice of the matching pool in a quadratic round: proportional to the
+// square of the sum of its contributors' square roots, out of the same squares
+// summed over every project.
+*/
+pub fn quadratic_match(matching_pool: i128, sqrt_sum: i128, total_squares: i128) -> i128 {
+    share(matching_pool, sqrt_sum * sqrt_sum, total_squares)
+}
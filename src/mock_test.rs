@@ -0,0 +1,142 @@
+#![cfg(all(test, feature = "mock-backend"))]
+
+/*
+// With the `mock-backend` feature the contract keeps balances in its own storage
+// instead of calling a token, so test.rs (which runs against the Stellar asset
+// contract) is left out and these run instead. Balances are funded with
+// MockBackend::mint, inside the contract's context since that's where they live.
+*/
+use super::backend::{MockBackend, PaymentBackend};
+use super::{
+    ClaimWindows, DepositLimits, ExceptionPolicy, OrganizerFee, PayoutMode,
+    ProfitDistributionContract, ProfitDistributionContractClient, Referrals, ShareMode, TimeBound,
+    TimeBoundKind,
+};
+use soroban_auth::Identifier;
+use soroban_sdk::{testutils::Accounts, vec, AccountId, BytesN, Env};
+
+const DEPOSIT_FEE: i128 = 10_000_000;
+
+struct Setup<'a> {
+    env: &'a Env,
+    admin: AccountId,
+    // never called, the mock backend ignores it
+    token_id: BytesN<32>,
+    contract: BytesN<32>,
+    client: ProfitDistributionContractClient,
+}
+
+fn setup(env: &Env) -> Setup {
+    let admin = env.accounts().generate();
+    let token_id = BytesN::from_array(env, &[0; 32]);
+    let contract = env.register_contract(None, ProfitDistributionContract);
+    let client = ProfitDistributionContractClient::new(env, &contract);
+
+    client.with_source_account(&admin).initialize(
+        &Identifier::Account(admin.clone()),
+        &env.ledger().timestamp(),
+        &DEPOSIT_FEE,
+        &token_id,
+        &ClaimWindows {
+            cohorts: 1,
+            spacing: 0,
+        },
+        &OrganizerFee {
+            bps: 0,
+            splits: vec![env],
+        },
+        &0,
+        &Referrals {
+            bonus_bps: 0,
+            max_per_referrer: 0,
+        },
+        &DepositLimits {
+            flexible: false,
+            min_deposit: 0,
+            max_deposit: 0,
+            pool_cap: 0,
+        },
+        &ShareMode::ProRata,
+        &0,
+        &0,
+        &ExceptionPolicy {
+            window: 0,
+            max_count: 0,
+        },
+        &None,
+        &vec![env],
+        &PayoutMode::Hybrid,
+        &0,
+    );
+
+    Setup {
+        env,
+        admin,
+        token_id,
+        contract,
+        client,
+    }
+}
+
+fn balance(s: &Setup, id: &Identifier) -> i128 {
+    s.env
+        .as_contract(&s.contract, || MockBackend.balance(s.env, id))
+}
+
+// mints the fee to a fresh account, the mock backend needs no allowance
+fn funded_user(s: &Setup) -> AccountId {
+    let user = s.env.accounts().generate();
+    let user_id = Identifier::Account(user.clone());
+    s.env.as_contract(&s.contract, || {
+        MockBackend::mint(s.env, &user_id, DEPOSIT_FEE)
+    });
+    user
+}
+
+fn deposit(s: &Setup, user: &AccountId) {
+    s.client.with_source_account(user).deposit(
+        &s.token_id,
+        &DEPOSIT_FEE,
+        &vec![s.env, Identifier::Account(user.clone())],
+        &TimeBound {
+            kind: TimeBoundKind::Before,
+            timestamp: s.env.ledger().timestamp() + 86400,
+        },
+        &None,
+        &None,
+        &0,
+        &None,
+        &None,
+    );
+}
+
+#[test]
+fn test_mock_backend_moves_deposits_and_payouts() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+    let user_id = Identifier::Account(user.clone());
+    let no_show = funded_user(&s);
+    let contract_id = Identifier::Contract(s.contract.clone());
+
+    deposit(&s, &user);
+    deposit(&s, &no_show);
+    assert_eq!(balance(&s, &user_id), 0);
+    assert_eq!(balance(&s, &contract_id), 2 * DEPOSIT_FEE);
+
+    s.client
+        .with_source_account(&s.admin)
+        .check_in_many(&vec![&env, user_id.clone()]);
+    s.client.with_source_account(&user).distribute();
+    assert_eq!(balance(&s, &user_id), 2 * DEPOSIT_FEE);
+    assert_eq!(balance(&s, &contract_id), 0);
+}
+
+#[test]
+fn test_mock_backend_reports_its_own_token_info() {
+    let env = Env::default();
+    let s = setup(&env);
+
+    let info = s.client.payout_token_info();
+    assert_eq!(info.decimals, 7);
+}
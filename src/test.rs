@@ -1,21 +1,3727 @@
-#![cfg(test)]
+// Runs against the Stellar asset contract, see mock_test.rs for the mock backend
+#![cfg(all(test, not(feature = "mock-backend")))]
 
-use super::{Contract, ContractClient};
-use soroban_sdk::{symbol, vec, Env};
+extern crate std;
 
-#[test]
-fn test() {
+use super::engine::{self, Phase};
+use super::testutils;
+use super::{
+    token, treasury_transfer, AutoExtension, Breakpoint, Bucket, Checkpoint, ClaimWindows, DataKey,
+    DepositLimits, DepositPermit, Direction, ExceptionPolicy, FeeSplit, GovernanceDrop, Milestone,
+    OrganizerFee, PayoutMode, PersonhoodGate, PiecewiseSchedule, ProfitDistributionContract,
+    ProfitDistributionContractClient, Referrals, Registration, Rejection, ShareMode, TimeBound,
+    TimeBoundKind, UsdFee, ACTION_CHECK_IN, ACTION_DEPOSIT, ACTION_DISTRIBUTE, CANCEL_COOLDOWN,
+    CLOSE_GRACE, EMERGENCY_DELAY, FEATURE_APPEALS, FEATURE_BATCH_DEPOSITS, FEATURE_DONATIONS,
+    FEATURE_EMERGENCY_WITHDRAWAL, FEATURE_FORFEIT_VOTE, FEATURE_REFERRALS, FEATURE_ROUNDS,
+    FEATURE_TIERS, FEATURE_TWO_STAFF_CHECK_IN,
+};
+use soroban_auth::{
+    testutils::ed25519::{self, sign},
+    Ed25519Signature, Identifier, Signature, SignaturePayload,
+};
+use soroban_sdk::{
+    contractimpl,
+    serde::Serialize,
+    symbol,
+    testutils::{ed25519::Sign, Accounts, Events, Ledger},
+    vec,
+    xdr::{ReadXdr, ScSpecEntry, ScSpecTypeDef},
+    AccountId, Bytes, BytesN, Env, RawVal, Symbol, TryIntoVal, Vec,
+};
+use std::{format, string::String};
+
+// one whole token at the stellar asset contract's 7 decimals
+const DEPOSIT_FEE: i128 = 10_000_000;
+
+// the fixture's meetup is held at UTC+02:00
+const UTC_OFFSET: i32 = 120;
+
+struct Setup<'a> {
+    env: &'a Env,
+    admin: AccountId,
+    token_id: BytesN<32>,
+    token: token::Client,
+    contract: BytesN<32>,
+    contract_id: Identifier,
+    client: ProfitDistributionContractClient,
+}
+
+// The initialize arguments individual tests want to vary
+struct Init {
+    features: u32,
+    organizer_fee: OrganizerFee,
+    reserve_bps: u32,
+    tier_capacities: Vec<u32>,
+    deposit_limits: DepositLimits,
+    referrals: Referrals,
+}
+
+fn default_init(env: &Env) -> Init {
+    Init {
+        features: 0,
+        organizer_fee: OrganizerFee {
+            bps: 0,
+            splits: vec![env],
+        },
+        reserve_bps: 0,
+        tier_capacities: vec![env],
+        deposit_limits: DepositLimits {
+            flexible: false,
+            min_deposit: 0,
+            max_deposit: 0,
+            pool_cap: 0,
+        },
+        referrals: Referrals {
+            bonus_bps: 0,
+            max_per_referrer: 0,
+        },
+    }
+}
+
+fn setup(env: &Env) -> Setup {
+    setup_with(env, default_init(env))
+}
+
+fn setup_with(env: &Env, init: Init) -> Setup {
     // In any test the first thing that is always required is an Env,
     // which is the Soroban environment that the contract will run inside of
-    let env = Env::default();
+    let admin = env.accounts().generate();
+    let token_id = env.register_stellar_asset_contract(admin.clone());
+    let token = token::Client::new(env, &token_id);
 
     // the first arg can be either 'contract ID' or 'None'
-    let contract_id = env.register_contract(None, Contract);
-    let client = ContractClient::new(&env, &contract_id);
+    let contract = env.register_contract(None, ProfitDistributionContract);
+    let client = ProfitDistributionContractClient::new(env, &contract);
+
+    initialize(env, &client, &admin, &token_id, init);
+
+    Setup {
+        env,
+        admin,
+        token_id,
+        token,
+        contract_id: Identifier::Contract(contract.clone()),
+        contract,
+        client,
+    }
+}
+
+fn initialize(
+    env: &Env,
+    client: &ProfitDistributionContractClient,
+    admin: &AccountId,
+    token_id: &BytesN<32>,
+    init: Init,
+) {
+    client.with_source_account(admin).initialize(
+        &Identifier::Account(admin.clone()),
+        &env.ledger().timestamp(),
+        &DEPOSIT_FEE,
+        token_id,
+        &ClaimWindows {
+            cohorts: 1,
+            spacing: 0,
+        },
+        &init.organizer_fee,
+        &init.reserve_bps,
+        &init.referrals,
+        &init.deposit_limits,
+        &ShareMode::ProRata,
+        &0,
+        &init.features,
+        &ExceptionPolicy {
+            window: 0,
+            max_count: 0,
+        },
+        &None,
+        &init.tier_capacities,
+        &PayoutMode::Hybrid,
+        &UTC_OFFSET,
+    );
+}
+
+// mints the fee to a fresh account and approves the contract to pull it
+fn funded_user(s: &Setup) -> AccountId {
+    let user = s.env.accounts().generate();
+    s.token.with_source_account(&s.admin).mint(
+        &Signature::Invoker,
+        &0,
+        &Identifier::Account(user.clone()),
+        &DEPOSIT_FEE,
+    );
+    testutils::approve_required_allowance(s.env, &s.contract, &s.token_id, &user);
+    user
+}
+
+fn deposit(s: &Setup, user: &AccountId) {
+    deposit_in_tier(s, user, 0);
+}
+
+fn deposit_in_tier(s: &Setup, user: &AccountId, tier: u32) {
+    s.client.with_source_account(user).deposit(
+        &s.token_id,
+        &DEPOSIT_FEE,
+        &vec![s.env, Identifier::Account(user.clone())],
+        &TimeBound {
+            kind: TimeBoundKind::Before,
+            timestamp: s.env.ledger().timestamp() + 86400,
+        },
+        &None,
+        &None,
+        &tier,
+        &None,
+        &None,
+    );
+}
+
+fn deposit_with_reg_id(s: &Setup, invoker: &AccountId, user: &AccountId, reg_id: &BytesN<16>) {
+    s.client.with_source_account(invoker).deposit(
+        &s.token_id,
+        &DEPOSIT_FEE,
+        &vec![s.env, Identifier::Account(user.clone())],
+        &TimeBound {
+            kind: TimeBoundKind::Before,
+            timestamp: s.env.ledger().timestamp() + 86400,
+        },
+        &None,
+        &Some(reg_id.clone()),
+        &0,
+        &None,
+        &None,
+    );
+}
+
+#[test]
+fn test_retried_reg_id_charges_once() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+    let reg_id = BytesN::from_array(&env, &[1; 16]);
+
+    deposit_with_reg_id(&s, &user, &user, &reg_id);
+    deposit_with_reg_id(&s, &user, &user, &reg_id);
+    assert_eq!(s.token.balance(&s.contract_id), DEPOSIT_FEE);
+}
+
+#[test]
+#[should_panic(expected = "this registration ID was already used for another address")]
+fn test_reg_id_is_bound_to_its_submitter() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+    let other = funded_user(&s);
+    let reg_id = BytesN::from_array(&env, &[1; 16]);
+
+    deposit_with_reg_id(&s, &user, &user, &reg_id);
+    // someone who saw the ID can't replay it, even for the same address
+    deposit_with_reg_id(&s, &other, &user, &reg_id);
+}
+
+#[test]
+#[should_panic(expected = "a deposit has to register at least one address")]
+fn test_deposit_rejects_no_depositers() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+
+    s.client.with_source_account(&user).deposit(
+        &s.token_id,
+        &DEPOSIT_FEE,
+        &vec![&env],
+        &TimeBound {
+            kind: TimeBoundKind::Before,
+            timestamp: env.ledger().timestamp() + 86400,
+        },
+        &None,
+        &None,
+        &0,
+        &None,
+        &None,
+    );
+}
+
+// an ed25519 identity registered (and checked in) by a funded account, for claims
+// it signs off-chain
+fn signing_attendee(
+    s: &Setup,
+) -> (
+    Identifier,
+    impl ed25519::Identifier + Sign<SignaturePayload, Signature = [u8; 64]>,
+) {
+    let (attendee_id, signer) = ed25519::generate(s.env);
+    let payer = funded_user(s);
+    s.client.with_source_account(&payer).deposit(
+        &s.token_id,
+        &DEPOSIT_FEE,
+        &vec![s.env, attendee_id.clone()],
+        &TimeBound {
+            kind: TimeBoundKind::Before,
+            timestamp: s.env.ledger().timestamp() + 86400,
+        },
+        &None,
+        &None,
+        &0,
+        &None,
+        &None,
+    );
+    s.client
+        .with_source_account(&s.admin)
+        .check_in_many(&vec![s.env, attendee_id.clone()]);
+    (attendee_id, signer)
+}
+
+// An ed25519 depositor holding the deposit fee, with the contract approved to pull it
+fn signing_depositor(
+    s: &Setup,
+) -> (
+    Identifier,
+    impl ed25519::Identifier + Sign<SignaturePayload, Signature = [u8; 64]>,
+) {
+    let (depositor_id, signer) = ed25519::generate(s.env);
+    s.token
+        .with_source_account(&s.admin)
+        .mint(&Signature::Invoker, &0, &depositor_id, &DEPOSIT_FEE);
+    let required = s.client.required_allowance(&depositor_id);
+    let sig = sign(
+        s.env,
+        &signer,
+        &s.token_id,
+        symbol!("approve"),
+        (&depositor_id, 0i128, &s.contract_id, required),
+    );
+    s.token.approve(&sig, &0, &s.contract_id, &required);
+    (depositor_id, signer)
+}
+
+fn deposit_permit(
+    s: &Setup,
+    signer: &(impl ed25519::Identifier + Sign<SignaturePayload, Signature = [u8; 64]>),
+    nonce: i128,
+) -> DepositPermit {
+    let signature = sign(
+        s.env,
+        signer,
+        &s.contract,
+        symbol!("deposit"),
+        (
+            &s.contract_id,
+            DEPOSIT_FEE,
+            0u32,
+            None::<BytesN<32>>,
+            None::<BytesN<32>>,
+            nonce,
+        ),
+    );
+    DepositPermit {
+        signature,
+        amount: DEPOSIT_FEE,
+        tier: 0,
+        terms_hash: None,
+        answers: None,
+        nonce,
+    }
+}
+
+// Puts `id`'s public key on an ed25519 signature made with another key
+fn forge(sig: Signature, id: &Identifier) -> Signature {
+    match (sig, id) {
+        (Signature::Ed25519(sig), Identifier::Ed25519(public_key)) => {
+            Signature::Ed25519(Ed25519Signature {
+                public_key: public_key.clone(),
+                signature: sig.signature,
+            })
+        }
+        _ => panic!("not an ed25519 signature"),
+    }
+}
+
+#[test]
+fn test_deposit_pulls_from_invoker() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+
+    deposit(&s, &user);
+
+    assert_eq!(s.token.balance(&Identifier::Account(user)), 0);
+    assert_eq!(s.token.balance(&s.contract_id), DEPOSIT_FEE);
+}
+
+#[test]
+fn test_distribute_pays_depositer() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+
+    deposit(&s, &user);
+    s.client
+        .with_source_account(&s.admin)
+        .check_in_many(&vec![&env, Identifier::Account(user.clone())]);
+    s.client.with_source_account(&user).distribute();
+
+    assert_eq!(s.token.balance(&Identifier::Account(user)), DEPOSIT_FEE);
+}
+
+// This contract's events called `name`, as (topics, data)
+fn contract_events(s: &Setup, name: Symbol) -> std::vec::Vec<(Vec<RawVal>, RawVal)> {
+    s.env
+        .events()
+        .all()
+        .iter()
+        .map(|event| event.unwrap())
+        .filter(|(contract, topics, _)| {
+            *contract == s.contract
+                && topics
+                    .get_unchecked(1)
+                    .unwrap()
+                    .try_into_val(s.env)
+                    .map_or(false, |topic: Symbol| topic == name)
+        })
+        .map(|(_, topics, data)| (topics, data))
+        .collect()
+}
+
+#[test]
+fn test_push_payouts_pays_checked_in_attendees() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+    let no_show = funded_user(&s);
+
+    deposit(&s, &user);
+    deposit(&s, &no_show);
+    s.client
+        .with_source_account(&s.admin)
+        .check_in_many(&vec![&env, Identifier::Account(user.clone())]);
+    s.client.with_source_account(&s.admin).push_payouts(&10);
+
+    assert_eq!(s.token.balance(&Identifier::Account(user)), 2 * DEPOSIT_FEE);
+    assert_eq!(s.token.balance(&Identifier::Account(no_show)), 0);
+}
+
+#[test]
+fn test_push_payouts_audits_every_batch() {
+    let env = Env::default();
+    let s = setup(&env);
+    let first = funded_user(&s);
+    let second = funded_user(&s);
+    let first_id = Identifier::Account(first.clone());
+    let second_id = Identifier::Account(second.clone());
+    let admin = s.client.with_source_account(&s.admin);
+
+    deposit(&s, &first);
+    deposit(&s, &second);
+    admin.check_in_many(&vec![&env, first_id.clone(), second_id.clone()]);
+    admin.push_payouts(&1);
+    admin.push_payouts(&1);
+
+    // the first payment settles the pool, both batches still agree on the meetup
+    let paid = contract_events(&s, symbol!("paid"));
+    assert_eq!(paid.len(), 2);
+    for ((topics, data), (addr, batch)) in paid.iter().zip([(&first_id, 0), (&second_id, 1)]) {
+        let paid_to: Identifier = topics.get_unchecked(2).unwrap().try_into_val(&env).unwrap();
+        let payout: (u32, i128, u32) = data.try_into_val(&env).unwrap();
+        assert_eq!(paid_to, *addr);
+        assert_eq!(payout, (0, DEPOSIT_FEE, batch));
+    }
+
+    let mut checksum = BytesN::from_array(&env, &[0; 32]);
+    for addr in [&first_id, &second_id] {
+        let leaf = env
+            .crypto()
+            .sha256(&(addr.clone(), DEPOSIT_FEE).serialize(&env));
+        let mut chained: Bytes = checksum.into();
+        chained.append(&leaf.into());
+        checksum = env.crypto().sha256(&chained);
+    }
+    let done = contract_events(&s, symbol!("dist_done"));
+    assert_eq!(done.len(), 1);
+    let (topics, data) = &done[0];
+    let meetup_id: u32 = topics.get_unchecked(2).unwrap().try_into_val(&env).unwrap();
+    let audit: (u32, i128, BytesN<32>) = data.try_into_val(&env).unwrap();
+    assert_eq!(meetup_id, 0);
+    assert_eq!(audit, (2, 2 * DEPOSIT_FEE, checksum));
+}
+
+// `payer` registers `attendee` and names `referrer` as who referred them
+fn deposit_referred(s: &Setup, payer: &AccountId, attendee: &Identifier, referrer: &Identifier) {
+    s.client.with_source_account(payer).deposit(
+        &s.token_id,
+        &DEPOSIT_FEE,
+        &vec![s.env, attendee.clone()],
+        &TimeBound {
+            kind: TimeBoundKind::Before,
+            timestamp: s.env.ledger().timestamp() + 86400,
+        },
+        &Some(referrer.clone()),
+        &None,
+        &0,
+        &None,
+        &None,
+    );
+}
+
+fn setup_referrals(env: &Env) -> Setup {
+    setup_with(
+        env,
+        Init {
+            features: FEATURE_REFERRALS,
+            referrals: Referrals {
+                bonus_bps: 1000,
+                max_per_referrer: 5,
+            },
+            ..default_init(env)
+        },
+    )
+}
+
+#[test]
+fn test_referral_bonus_comes_out_of_the_forfeits() {
+    let env = Env::default();
+    let s = setup_referrals(&env);
+    let referrer = funded_user(&s);
+    let referrer_id = Identifier::Account(referrer.clone());
+    let referred = env.accounts().generate();
+    let referred_id = Identifier::Account(referred.clone());
+    let payer = funded_user(&s);
+    let no_show = funded_user(&s);
+
+    deposit(&s, &referrer);
+    // the referral is credited to the registered attendee, not whoever paid
+    deposit_referred(&s, &payer, &referred_id, &referrer_id);
+    deposit(&s, &no_show);
+    s.client.with_source_account(&s.admin).check_in_many(&vec![
+        &env,
+        referrer_id.clone(),
+        referred_id.clone(),
+    ]);
+
+    s.client.with_source_account(&referred).distribute();
+    let share = s.token.balance(&referred_id);
+    assert_eq!(s.token.balance(&referrer_id), share / 10);
+    s.client.with_source_account(&referrer).distribute();
+    // both attendees get the same share, the bonus came from the no-show
+    assert_eq!(s.token.balance(&referrer_id), share + share / 10);
+}
+
+#[test]
+fn test_referrer_has_to_attend_for_the_bonus() {
+    let env = Env::default();
+    let s = setup_referrals(&env);
+    let referrer = funded_user(&s);
+    let referrer_id = Identifier::Account(referrer.clone());
+    let referred = funded_user(&s);
+    let referred_id = Identifier::Account(referred.clone());
+
+    deposit(&s, &referrer);
+    deposit_referred(&s, &referred, &referred_id, &referrer_id);
+    s.client
+        .with_source_account(&s.admin)
+        .check_in_many(&vec![&env, referred_id.clone()]);
+
+    s.client.with_source_account(&referred).distribute();
+    assert_eq!(s.token.balance(&referred_id), 2 * DEPOSIT_FEE);
+    assert_eq!(s.token.balance(&referrer_id), 0);
+}
+
+#[test]
+fn test_outflow_cap_spreads_payouts_over_ledgers() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+    let other = funded_user(&s);
+
+    s.client.with_source_account(&s.admin).set_outflow_cap(&DEPOSIT_FEE);
+    deposit(&s, &user);
+    deposit(&s, &other);
+    s.client.with_source_account(&s.admin).check_in_many(&vec![
+        &env,
+        Identifier::Account(user.clone()),
+        Identifier::Account(other.clone()),
+    ]);
+    s.client.with_source_account(&s.admin).push_payouts(&1);
+    assert_eq!(s.client.outflow_left(), Some(0));
+
+    env.ledger().with_mut(|ledger| ledger.sequence_number += 1);
+    assert_eq!(s.client.outflow_left(), Some(DEPOSIT_FEE));
+    s.client.with_source_account(&s.admin).push_payouts(&1);
+
+    assert_eq!(s.token.balance(&Identifier::Account(user)), DEPOSIT_FEE);
+    assert_eq!(s.token.balance(&Identifier::Account(other)), DEPOSIT_FEE);
+}
+
+#[test]
+#[should_panic(expected = "Status(ContractError(15))")]
+fn test_outflow_cap_stops_payouts_within_a_ledger() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+    let other = funded_user(&s);
+
+    s.client.with_source_account(&s.admin).set_outflow_cap(&DEPOSIT_FEE);
+    deposit(&s, &user);
+    deposit(&s, &other);
+    s.client.with_source_account(&s.admin).check_in_many(&vec![
+        &env,
+        Identifier::Account(user.clone()),
+        Identifier::Account(other.clone()),
+    ]);
+    s.client.with_source_account(&s.admin).push_payouts(&2);
+}
+
+#[test]
+fn test_locked_payout_table_can_be_proven() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+    let user_id = Identifier::Account(user.clone());
+    let no_proof: Vec<BytesN<32>> = vec![&env];
+
+    deposit(&s, &user);
+    s.client
+        .with_source_account(&s.admin)
+        .check_in_many(&vec![&env, user_id.clone()]);
+    assert!(s.client.payout_root().is_none());
+    s.client.with_source_account(&s.admin).lock_distribution();
+
+    // a single leaf is its own root
+    assert!(s.client.verify_payout_proof(&user_id, &DEPOSIT_FEE, &no_proof));
+    assert!(!s.client.verify_payout_proof(&user_id, &(DEPOSIT_FEE + 1), &no_proof));
+}
+
+// Attendance leaves are the sha256 of the serialized address
+fn attendance_leaf(env: &Env, user: &AccountId) -> BytesN<32> {
+    env.crypto()
+        .sha256(&Identifier::Account(user.clone()).serialize(env))
+}
+
+#[test]
+fn test_attendance_proof_is_checked_against_the_root() {
+    let env = Env::default();
+    let s = setup(&env);
+    let (user, other, no_show) = (funded_user(&s), funded_user(&s), funded_user(&s));
+    let user_id = Identifier::Account(user.clone());
+    let admin = s.client.with_source_account(&s.admin);
+
+    deposit(&s, &user);
+    deposit(&s, &other);
+    deposit(&s, &no_show);
+    admin.check_in_many(&vec![
+        &env,
+        user_id.clone(),
+        Identifier::Account(other.clone()),
+    ]);
+    let proof = vec![&env, attendance_leaf(&env, &other)];
+    // always false before settlement
+    assert!(!s.client.verify_attendance_proof(&user_id, &proof));
+
+    admin.lock_distribution();
+    assert!(s.client.verify_attendance_proof(&user_id, &proof));
+    assert!(s.client.verify_attendance_proof(
+        &Identifier::Account(other.clone()),
+        &vec![&env, attendance_leaf(&env, &user)]
+    ));
+    // a tampered proof or a no-show don't verify
+    let tampered = vec![&env, attendance_leaf(&env, &no_show)];
+    assert!(!s.client.verify_attendance_proof(&user_id, &tampered));
+    assert!(!s
+        .client
+        .verify_attendance_proof(&Identifier::Account(no_show), &proof));
+}
+
+#[test]
+fn test_attendees_root_is_fixed_at_settlement() {
+    let env = Env::default();
+    let s = setup(&env);
+    let (user, other) = (funded_user(&s), funded_user(&s));
+    let admin = s.client.with_source_account(&s.admin);
+
+    deposit(&s, &user);
+    deposit(&s, &other);
+    admin.check_in_many(&vec![
+        &env,
+        Identifier::Account(user.clone()),
+        Identifier::Account(other.clone()),
+    ]);
+    assert!(s.client.attendees_root().is_none());
+    admin.lock_distribution();
+
+    // two leaves hash together in sorted order
+    let (a, b) = (attendance_leaf(&env, &user), attendance_leaf(&env, &other));
+    let (low, high) = if a < b { (a, b) } else { (b, a) };
+    let mut pair = Bytes::new(&env);
+    pair.append(&low.into());
+    pair.append(&high.into());
+    assert_eq!(s.client.attendees_root(), Some(env.crypto().sha256(&pair)));
+}
+
+#[test]
+fn test_execute_distribution_pays_the_locked_table_in_batches() {
+    let env = Env::default();
+    let s = setup(&env);
+    let users = [funded_user(&s), funded_user(&s), funded_user(&s)];
+    let admin = s.client.with_source_account(&s.admin);
+
+    let mut attendees = vec![&env];
+    for user in users.iter() {
+        deposit(&s, user);
+        attendees.push_back(Identifier::Account(user.clone()));
+    }
+    admin.check_in_many(&attendees);
+    admin.lock_distribution();
+
+    admin.execute_distribution(&1);
+    assert_eq!(
+        s.token.balance(&attendees.get_unchecked(0).unwrap()),
+        DEPOSIT_FEE
+    );
+    assert_eq!(s.token.balance(&attendees.get_unchecked(1).unwrap()), 0);
+    // an empty batch isn't counted, and a huge one stops at the last registrant
+    admin.execute_distribution(&0);
+    admin.execute_distribution(&u32::MAX);
+    for attendee in attendees.iter() {
+        assert_eq!(s.token.balance(&attendee.unwrap()), DEPOSIT_FEE);
+    }
+
+    let batches: std::vec::Vec<u32> = contract_events(&s, symbol!("paid"))
+        .iter()
+        .map(|(_, data)| {
+            let (_, _, batch): (u32, i128, u32) = data.try_into_val(&env).unwrap();
+            batch
+        })
+        .collect();
+    assert_eq!(batches, [0, 1, 1]);
+    let done = contract_events(&s, symbol!("dist_done"));
+    assert_eq!(done.len(), 1);
+    let (recipients, paid, _): (u32, i128, BytesN<32>) = done[0].1.try_into_val(&env).unwrap();
+    assert_eq!((recipients, paid), (3, 3 * DEPOSIT_FEE));
+}
+
+#[test]
+fn test_export_state_pages_past_the_end_are_empty() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+
+    deposit(&s, &user);
+    assert_eq!(s.client.export_state(&0).len(), 1);
+    assert!(s.client.export_state(&1).is_empty());
+    // would overflow the page offset
+    assert!(s.client.export_state(&u32::MAX).is_empty());
+}
+
+#[test]
+fn test_membership_views_outlive_the_meetup() {
+    let env = Env::default();
+    let s = setup(&env);
+    let attendee = funded_user(&s);
+    let user = Identifier::Account(attendee.clone());
+    let no_show = funded_user(&s);
+    let no_show_id = Identifier::Account(no_show.clone());
+    let stranger = Identifier::Account(env.accounts().generate());
+
+    deposit(&s, &attendee);
+    deposit(&s, &no_show);
+    s.client
+        .with_source_account(&s.admin)
+        .check_in_many(&vec![&env, user.clone()]);
+    assert!(s.client.attended(&0, &user));
+    assert!(!s.client.attended(&0, &no_show_id));
+    assert!(s.client.registered(&0, &no_show_id));
+
+    s.client.with_source_account(&s.admin).lock_distribution();
+    assert!(s.client.attended(&0, &user));
+    assert!(s.client.registered(&0, &no_show_id));
+    assert!(!s.client.attended(&0, &no_show_id));
+    assert!(!s.client.registered(&0, &stranger));
+    assert!(!s.client.registered(&1, &user));
+}
+
+#[test]
+fn test_membership_survives_close_without_a_lock() {
+    let env = Env::default();
+    let s = setup(&env);
+    let attendee = funded_user(&s);
+    let user = Identifier::Account(attendee.clone());
+    let no_show = funded_user(&s);
+    let no_show_id = Identifier::Account(no_show.clone());
+
+    deposit(&s, &attendee);
+    deposit(&s, &no_show);
+    s.client
+        .with_source_account(&s.admin)
+        .check_in_many(&vec![&env, user.clone()]);
+    s.client.with_source_account(&attendee).distribute();
+    s.client
+        .with_source_account(&s.admin)
+        .close(&Identifier::Account(s.admin.clone()));
+
+    assert!(s.client.attended(&0, &user));
+    assert!(s.client.registered(&0, &no_show_id));
+    assert!(!s.client.attended(&0, &no_show_id));
+}
+
+#[test]
+fn test_released_appeal_restores_the_claim() {
+    let env = Env::default();
+    let s = setup_with(
+        &env,
+        Init {
+            features: FEATURE_APPEALS,
+            ..default_init(&env)
+        },
+    );
+    let user = funded_user(&s);
+    let user_id = Identifier::Account(user.clone());
+    let admin = s.client.with_source_account(&s.admin);
+
+    deposit(&s, &user);
+    admin.check_in_many(&vec![&env, user_id.clone()]);
+    admin.remove_attendee(&user_id);
+    assert_eq!(s.client.escrow(&user_id).amount, DEPOSIT_FEE);
+    // removing someone doesn't settle the pool
+    assert_eq!(s.client.events_count(), 0);
 
-    let words = client.hello(&symbol!("Dev"));
+    admin.resolve_appeal(&user_id, &true);
+    s.client.with_source_account(&user).distribute();
+    assert_eq!(s.token.balance(&user_id), DEPOSIT_FEE);
+}
+
+#[test]
+fn test_denied_appeal_returns_the_share_to_attendees() {
+    let env = Env::default();
+    let s = setup_with(
+        &env,
+        Init {
+            features: FEATURE_APPEALS,
+            ..default_init(&env)
+        },
+    );
+    let user = funded_user(&s);
+    let other = funded_user(&s);
+    let removed = Identifier::Account(other.clone());
+    let admin = s.client.with_source_account(&s.admin);
+
+    deposit(&s, &user);
+    deposit(&s, &other);
+    admin.check_in_many(&vec![&env, Identifier::Account(user.clone()), removed.clone()]);
+    admin.remove_attendee(&removed);
+    admin.resolve_appeal(&removed, &false);
+    s.client.with_source_account(&user).distribute();
+
+    assert_eq!(s.token.balance(&Identifier::Account(user)), 2 * DEPOSIT_FEE);
+    assert_eq!(s.client.forfeited(), 0);
+}
+
+#[test]
+#[should_panic(expected = "an appeal is still pending")]
+fn test_pending_appeal_holds_up_settlement() {
+    let env = Env::default();
+    let s = setup_with(
+        &env,
+        Init {
+            features: FEATURE_APPEALS,
+            ..default_init(&env)
+        },
+    );
+    let user = funded_user(&s);
+    let other = funded_user(&s);
+    let removed = Identifier::Account(other.clone());
+    let admin = s.client.with_source_account(&s.admin);
+
+    deposit(&s, &user);
+    deposit(&s, &other);
+    admin.check_in_many(&vec![&env, Identifier::Account(user.clone()), removed.clone()]);
+    admin.remove_attendee(&removed);
+    s.client.with_source_account(&user).distribute();
+}
+
+// An appeals event whose organizer fee is split between three co-hosts
+fn setup_cohosts(env: &Env) -> (Setup, [AccountId; 3]) {
+    let cohosts = [
+        env.accounts().generate(),
+        env.accounts().generate(),
+        env.accounts().generate(),
+    ];
+    let mut splits = vec![env];
+    for (cohost, bps) in cohosts.iter().zip([3334, 3333, 3333]) {
+        splits.push_back(FeeSplit {
+            recipient: Identifier::Account(cohost.clone()),
+            bps,
+        });
+    }
+    let s = setup_with(
+        env,
+        Init {
+            features: FEATURE_APPEALS,
+            organizer_fee: OrganizerFee { bps: 0, splits },
+            ..default_init(env)
+        },
+    );
+    (s, cohosts)
+}
+
+#[test]
+fn test_appeal_is_decided_by_a_cohost_majority() {
+    let env = Env::default();
+    let (s, cohosts) = setup_cohosts(&env);
+    let user = funded_user(&s);
+    let user_id = Identifier::Account(user.clone());
+    let admin = s.client.with_source_account(&s.admin);
+
+    deposit(&s, &user);
+    admin.check_in_many(&vec![&env, user_id.clone()]);
+    admin.remove_attendee(&user_id);
+
+    s.client
+        .with_source_account(&cohosts[0])
+        .resolve_appeal(&user_id, &true);
+    // one vote out of three isn't a majority yet
+    assert_eq!(s.client.escrow(&user_id).releases.len(), 1);
+    s.client
+        .with_source_account(&cohosts[1])
+        .resolve_appeal(&user_id, &true);
+    s.client.with_source_account(&user).distribute();
+    assert_eq!(s.token.balance(&user_id), DEPOSIT_FEE);
+}
+
+#[test]
+#[should_panic(expected = "only co-hosts can decide appeals")]
+fn test_admin_cannot_decide_an_appeal_alone() {
+    let env = Env::default();
+    let (s, _) = setup_cohosts(&env);
+    let user = funded_user(&s);
+    let user_id = Identifier::Account(user.clone());
+    let admin = s.client.with_source_account(&s.admin);
+
+    deposit(&s, &user);
+    admin.check_in_many(&vec![&env, user_id.clone()]);
+    admin.remove_attendee(&user_id);
+    admin.resolve_appeal(&user_id, &false);
+}
+
+#[test]
+fn test_expenses_under_the_cap_are_paid_right_away() {
+    let env = Env::default();
+    let (s, cohosts) = setup_cohosts(&env);
+    let admin_id = Identifier::Account(s.admin.clone());
+    let receipt = BytesN::from_array(&env, &[7; 32]);
+
+    s.client
+        .with_source_account(&s.admin)
+        .set_expense_cap(&(DEPOSIT_FEE / 2));
+    deposit(&s, &funded_user(&s));
+    deposit(&s, &funded_user(&s));
+    env.ledger().with_mut(|ledger| ledger.timestamp += 1);
+
+    let admin = s.client.with_source_account(&s.admin);
+    let id = admin.submit_expense(&(DEPOSIT_FEE / 4), &receipt);
+    assert!(s.client.expenses().get_unchecked(id).unwrap().paid);
+    assert_eq!(s.token.balance(&admin_id), DEPOSIT_FEE / 4);
+
+    // this one takes the reimbursements past the cap
+    let id = admin.submit_expense(&(DEPOSIT_FEE / 2), &receipt);
+    assert!(!s.client.expenses().get_unchecked(id).unwrap().paid);
+
+    // one co-host out of three isn't a majority yet
+    s.client
+        .with_source_account(&cohosts[0])
+        .approve_expense(&id);
+    assert!(!s.client.expenses().get_unchecked(id).unwrap().paid);
+    assert_eq!(s.token.balance(&admin_id), DEPOSIT_FEE / 4);
+
+    s.client
+        .with_source_account(&cohosts[1])
+        .approve_expense(&id);
+    assert!(s.client.expenses().get_unchecked(id).unwrap().paid);
+    assert_eq!(s.token.balance(&admin_id), DEPOSIT_FEE * 3 / 4);
+}
+
+#[test]
+#[should_panic(expected = "only co-hosts can approve expenses")]
+fn test_expense_approval_requires_a_cohost() {
+    let env = Env::default();
+    let (s, _) = setup_cohosts(&env);
+    let user = funded_user(&s);
+
+    deposit(&s, &user);
+    env.ledger().with_mut(|ledger| ledger.timestamp += 1);
+    let id = s
+        .client
+        .with_source_account(&s.admin)
+        .submit_expense(&(DEPOSIT_FEE / 2), &BytesN::from_array(&env, &[7; 32]));
+
+    s.client.with_source_account(&user).approve_expense(&id);
+}
+
+#[test]
+fn test_first_claim_records_the_meetup() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+    let no_show = funded_user(&s);
+
+    deposit(&s, &user);
+    deposit(&s, &no_show);
+    s.client
+        .with_source_account(&s.admin)
+        .check_in_many(&vec![&env, Identifier::Account(user.clone())]);
+    assert_eq!(s.client.events_count(), 0);
+    s.client.with_source_account(&user).distribute();
+
+    assert_eq!(s.client.events_count(), 1);
+    let stats = s.client.past_event(&0);
+    assert_eq!(stats.registered, 2);
+    assert_eq!(stats.attendance, 1);
+    assert_eq!(stats.pool, 2 * DEPOSIT_FEE);
+    assert_eq!(stats.distributed, 2 * DEPOSIT_FEE);
+}
+
+#[test]
+fn test_priority_buyers_move_to_the_front() {
+    let env = Env::default();
+    let s = setup(&env);
+    let early = funded_user(&s);
+    let late = funded_user(&s);
+    let late_id = Identifier::Account(late.clone());
+    let surcharge = DEPOSIT_FEE / 10;
+
+    s.client
+        .with_source_account(&s.admin)
+        .set_priority_surcharge(&surcharge);
+    deposit(&s, &early);
+    deposit(&s, &late);
+    s.token
+        .with_source_account(&s.admin)
+        .mint(&Signature::Invoker, &0, &late_id, &surcharge);
+    s.token
+        .with_source_account(&late)
+        .approve(&Signature::Invoker, &0, &s.contract_id, &surcharge);
+    s.client.with_source_account(&late).buy_priority();
+
+    assert_eq!(s.client.export_state(&0).get_unchecked(0).unwrap().addr, late_id);
+    assert_eq!(s.token.balance(&s.contract_id), 2 * DEPOSIT_FEE + surcharge);
+    // tracked apart from the deposit, like a sponsor contribution
+    let registration: Registration = env.as_contract(&s.contract, || {
+        env.storage().get_unchecked(DataKey::User(late_id)).unwrap()
+    });
+    assert_eq!(
+        (registration.amount, registration.extra),
+        (DEPOSIT_FEE, surcharge)
+    );
+}
+
+#[test]
+fn test_free_rsvps_are_members_but_not_paid() {
+    let env = Env::default();
+    let s = setup(&env);
+    let attendee = funded_user(&s);
+    let user = Identifier::Account(attendee.clone());
+    let guest = env.accounts().generate();
+    let guest_id = Identifier::Account(guest.clone());
+
+    s.client.with_source_account(&s.admin).set_rsvp_capacity(&1);
+    s.client.with_source_account(&guest).rsvp();
+    deposit(&s, &attendee);
+    s.client
+        .with_source_account(&s.admin)
+        .check_in_many(&vec![&env, user.clone(), guest_id.clone()]);
+    assert!(s.client.attended(&0, &guest_id));
+    assert_eq!(s.client.rsvp_count(), 1);
+
+    s.client.with_source_account(&s.admin).lock_distribution();
+    assert_eq!(s.client.share_of(&user), DEPOSIT_FEE);
+    assert!(s.client.attended(&0, &guest_id));
+}
+
+#[test]
+#[should_panic(expected = "Status(ContractError(7))")]
+fn test_free_rsvps_are_capped() {
+    let env = Env::default();
+    let s = setup(&env);
+
+    s.client.with_source_account(&s.admin).set_rsvp_capacity(&1);
+    s.client.with_source_account(&env.accounts().generate()).rsvp();
+    s.client.with_source_account(&env.accounts().generate()).rsvp();
+}
+
+#[test]
+fn test_deposit_proof_points_at_the_registration() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+
+    deposit(&s, &user);
+    let receipt = s.client.deposit_proof(&Identifier::Account(user.clone()));
+
+    assert_eq!(receipt.contract, s.contract);
+    assert_eq!(receipt.registration.amount, DEPOSIT_FEE);
+    assert!(matches!(receipt.key, DataKey::User(addr) if addr == Identifier::Account(user)));
+}
+
+#[test]
+fn test_claim_statuses_cover_a_page() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+    let no_show = funded_user(&s);
+    deposit(&s, &user);
+    deposit(&s, &no_show);
+    let user = Identifier::Account(user);
+    let no_show = Identifier::Account(no_show);
+
+    let reason = Bytes::from_slice(&env, b"broke the code of conduct");
+    let admin = s.client.with_source_account(&s.admin);
+    admin.check_in_many(&vec![&env, user.clone()]);
+    admin.block(&no_show);
+    admin.set_rejection_reason(&Rejection::Blocked, &reason);
+    let statuses = s.client.claim_statuses(&vec![&env, user, no_show]);
+
+    assert_eq!(statuses.len(), 2);
+    assert!(statuses.get_unchecked(0).unwrap().checked_in);
+    assert!(statuses.get_unchecked(0).unwrap().reason.is_none());
+    assert!(!statuses.get_unchecked(1).unwrap().checked_in);
+    assert_eq!(statuses.get_unchecked(1).unwrap().reason, Some(reason));
+}
+
+#[test]
+fn test_dust_shares_are_kept_back() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+    let user_id = Identifier::Account(user.clone());
+
+    s.client
+        .with_source_account(&s.admin)
+        .set_dust_threshold(&(2 * DEPOSIT_FEE));
+    deposit(&s, &user);
+    s.client
+        .with_source_account(&s.admin)
+        .check_in_many(&vec![&env, user_id.clone()]);
+    s.client.with_source_account(&s.admin).push_payouts(&10);
+
+    assert_eq!(s.token.balance(&user_id), 0);
+    assert_eq!(s.client.dust_of(&user_id), DEPOSIT_FEE);
+    assert_eq!(s.token.balance(&s.contract_id), DEPOSIT_FEE);
+}
+
+#[test]
+fn test_attendees_receive_the_governance_drop() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+    let user_id = Identifier::Account(user.clone());
+
+    // the meetup contract has to administer the governance token to mint it
+    let gov_id = env.register_stellar_asset_contract(s.admin.clone());
+    let gov = token::Client::new(&env, &gov_id);
+    gov.with_source_account(&s.admin)
+        .set_admin(&Signature::Invoker, &0, &s.contract_id);
+    s.client
+        .with_source_account(&s.admin)
+        .set_governance_drop(&GovernanceDrop {
+            token: gov_id,
+            amount: 500,
+            by_weight: false,
+        });
+
+    deposit(&s, &user);
+    s.client
+        .with_source_account(&s.admin)
+        .check_in_many(&vec![&env, user_id.clone()]);
+    s.client.with_source_account(&user).distribute();
+
+    assert_eq!(gov.balance(&user_id), 500);
+}
+
+#[test]
+#[should_panic(expected = "not eligible to receive any deposit back")]
+fn test_distribute_rejects_non_depositer() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+    let stranger = env.accounts().generate();
+
+    deposit(&s, &user);
+    s.client.with_source_account(&stranger).distribute();
+}
+
+#[test]
+#[should_panic(expected = "this attendee wasn't checked in at the meetup")]
+fn test_distribute_rejects_no_show() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+
+    deposit(&s, &user);
+    s.client.with_source_account(&user).distribute();
+}
+
+#[test]
+#[should_panic(expected = "only door staff can check attendees in")]
+fn test_check_in_requires_staff() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+
+    deposit(&s, &user);
+    s.client
+        .with_source_account(&user)
+        .check_in_many(&vec![&env, Identifier::Account(user.clone())]);
+}
+
+#[test]
+fn test_session_key_can_check_in() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+    let kiosk = env.accounts().generate();
+
+    deposit(&s, &user);
+    s.client.with_source_account(&s.admin).add_session_key(
+        &Identifier::Account(kiosk.clone()),
+        &(env.ledger().sequence() + 100),
+    );
+    s.client
+        .with_source_account(&kiosk)
+        .check_in_many(&vec![&env, Identifier::Account(user.clone())]);
+
+    assert!(s.client.export_state(&0).get_unchecked(0).unwrap().checked_in);
+}
+
+#[test]
+fn test_two_staff_check_in_waits_for_a_second_staff_member() {
+    let env = Env::default();
+    let s = setup_with(
+        &env,
+        Init {
+            features: FEATURE_TWO_STAFF_CHECK_IN,
+            ..default_init(&env)
+        },
+    );
+    let user = funded_user(&s);
+    let user_id = Identifier::Account(user.clone());
+    let staff = env.accounts().generate();
+    let admin = s.client.with_source_account(&s.admin);
+
+    deposit(&s, &user);
+    admin.add_staff(&Identifier::Account(staff.clone()));
+    admin.check_in_many(&vec![&env, user_id.clone()]);
     assert_eq!(
-        words,
-        vec![&env, symbol!("Hello"), symbol!("Dev"),]
+        s.client.pending_check_in(&user_id),
+        Some(Identifier::Account(s.admin.clone()))
+    );
+    assert!(
+        !s.client
+            .export_state(&0)
+            .get_unchecked(0)
+            .unwrap()
+            .checked_in
+    );
+
+    // confirming twice as the same staff member doesn't complete it
+    admin.check_in_many(&vec![&env, user_id.clone()]);
+    assert!(
+        !s.client
+            .export_state(&0)
+            .get_unchecked(0)
+            .unwrap()
+            .checked_in
+    );
+
+    s.client
+        .with_source_account(&staff)
+        .check_in_many(&vec![&env, user_id.clone()]);
+    assert_eq!(s.client.pending_check_in(&user_id), None);
+    assert!(
+        s.client
+            .export_state(&0)
+            .get_unchecked(0)
+            .unwrap()
+            .checked_in
+    );
+}
+
+#[test]
+#[should_panic(expected = "only door staff can check attendees in")]
+fn test_revoked_session_key_cannot_check_in() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+    let kiosk = env.accounts().generate();
+    let kiosk_id = Identifier::Account(kiosk.clone());
+
+    deposit(&s, &user);
+    s.client
+        .with_source_account(&s.admin)
+        .add_session_key(&kiosk_id, &(env.ledger().sequence() + 100));
+    s.client.with_source_account(&s.admin).revoke_session_key(&kiosk_id);
+    s.client
+        .with_source_account(&kiosk)
+        .check_in_many(&vec![&env, Identifier::Account(user.clone())]);
+}
+
+/*
+// The auth surface. This SDK predates env.mock_auths() and require_auth: a call
+// is authorized by whoever invokes it, so these tests pick the invoker with
+// with_source_account instead of mocking auths, and check that any other
+// invoker is turned away. There's no sweep entrypoint either, close is what
+// sweeps the pool, so it's tested in its place.
+*/
+#[test]
+#[should_panic(expected = "only the admin can perform this action")]
+fn test_block_requires_admin() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = env.accounts().generate();
+
+    s.client
+        .with_source_account(&user)
+        .block(&Identifier::Account(user.clone()));
+}
+
+#[test]
+#[should_panic(expected = "Status(ContractError(8))")]
+fn test_deposit_only_pulls_from_the_invoker() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+    let stranger = env.accounts().generate();
+
+    // the user approved the contract, but a deposit naming them is still paid
+    // by whoever invokes it
+    s.client.with_source_account(&stranger).deposit(
+        &s.token_id,
+        &DEPOSIT_FEE,
+        &vec![&env, Identifier::Account(user.clone())],
+        &TimeBound {
+            kind: TimeBoundKind::Before,
+            timestamp: env.ledger().timestamp() + 86400,
+        },
+        &None,
+        &None,
+        &0,
+        &None,
+        &None,
     );
-}
\ No newline at end of file
+}
+
+#[test]
+#[should_panic(expected = "not eligible to receive any deposit back")]
+fn test_distribute_only_pays_the_invoker() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+    let stranger = env.accounts().generate();
+
+    deposit(&s, &user);
+    s.client
+        .with_source_account(&s.admin)
+        .check_in_many(&vec![&env, Identifier::Account(user.clone())]);
+    s.client.with_source_account(&stranger).distribute();
+}
+
+#[test]
+#[should_panic(expected = "only the admin can perform this action")]
+fn test_sweep_requires_admin() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+    let user_id = Identifier::Account(user.clone());
+
+    deposit(&s, &user);
+    s.client
+        .with_source_account(&s.admin)
+        .check_in_many(&vec![&env, user_id.clone()]);
+    s.client.with_source_account(&user).distribute();
+    s.client.with_source_account(&user).close(&user_id);
+}
+
+#[test]
+#[should_panic(expected = "this address is blocked by the admin")]
+fn test_blocked_address_cannot_deposit() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+
+    s.client
+        .with_source_account(&s.admin)
+        .block(&Identifier::Account(user.clone()));
+    deposit(&s, &user);
+}
+
+#[test]
+#[should_panic(expected = "this address is blocked by the admin")]
+fn test_blocked_address_cannot_claim() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+    let user_id = Identifier::Account(user.clone());
+    let admin = s.client.with_source_account(&s.admin);
+
+    deposit(&s, &user);
+    admin.check_in_many(&vec![&env, user_id.clone()]);
+    admin.block(&user_id);
+    s.client.with_source_account(&user).distribute();
+}
+
+#[test]
+#[should_panic(expected = "this address is blocked by the admin")]
+fn test_blocked_address_cannot_receive_a_payout() {
+    let env = Env::default();
+    let s = setup(&env);
+    let secret = Bytes::from_slice(&env, b"lost key voucher");
+    let user = voucher_setup(&s, &secret);
+    let new_key = env.accounts().generate();
+
+    s.client
+        .with_source_account(&s.admin)
+        .block(&Identifier::Account(new_key.clone()));
+    s.client
+        .commit_voucher_claim(&voucher_commitment(&env, &secret, &new_key));
+    env.ledger().with_mut(|ledger| ledger.sequence_number += 1);
+    // the attendee isn't blocked, the address they claim to is
+    s.client
+        .with_source_account(&new_key)
+        .claim_with_voucher(&user, &secret);
+}
+
+#[test]
+fn test_transferred_registration_claims_for_the_new_holder() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+    let friend = env.accounts().generate();
+    let friend_id = Identifier::Account(friend.clone());
+    let admin = s.client.with_source_account(&s.admin);
+
+    deposit(&s, &user);
+    s.client
+        .with_source_account(&user)
+        .transfer_registration(&friend_id);
+    assert_eq!(
+        s.client.export_state(&0).get_unchecked(0).unwrap().addr,
+        friend_id
+    );
+
+    admin.check_in_many(&vec![&env, friend_id.clone()]);
+    s.client.with_source_account(&friend).distribute();
+    assert_eq!(s.token.balance(&friend_id), DEPOSIT_FEE);
+}
+
+#[test]
+#[should_panic(expected = "this address is blocked by the admin")]
+fn test_registration_cannot_be_transferred_to_a_blocked_address() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+    let blocked = Identifier::Account(env.accounts().generate());
+
+    deposit(&s, &user);
+    s.client.with_source_account(&s.admin).block(&blocked);
+    s.client
+        .with_source_account(&user)
+        .transfer_registration(&blocked);
+}
+
+#[test]
+#[should_panic(expected = "this address is blocked by the admin")]
+fn test_blocked_referrer_is_refused_at_deposit() {
+    let env = Env::default();
+    let s = setup_referrals(&env);
+    let user = funded_user(&s);
+    let referrer = Identifier::Account(env.accounts().generate());
+
+    s.client.with_source_account(&s.admin).block(&referrer);
+    deposit_referred(&s, &user, &Identifier::Account(user.clone()), &referrer);
+}
+
+#[test]
+#[should_panic(expected = "nonce should be zero for Invoker")]
+fn test_claim_for_rejects_nonce_for_invoker_signature() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+    let relayer = env.accounts().generate();
+
+    deposit(&s, &user);
+
+    // an invoker signature only ever speaks for the relayer itself, so it
+    // can't carry a nonce for somebody else's claim
+    s.client
+        .with_source_account(&relayer)
+        .claim_for(&Signature::Invoker, &1);
+}
+
+#[test]
+fn test_claim_for_pays_the_signer() {
+    let env = Env::default();
+    let s = setup(&env);
+    let (attendee_id, signer) = signing_attendee(&s);
+    let relayer = env.accounts().generate();
+
+    let sig = sign(
+        &env,
+        &signer,
+        &s.contract,
+        symbol!("claim_for"),
+        (&attendee_id, 0i128),
+    );
+    s.client.with_source_account(&relayer).claim_for(&sig, &0);
+
+    assert_eq!(s.token.balance(&attendee_id), DEPOSIT_FEE);
+    assert_eq!(s.token.balance(&Identifier::Account(relayer)), 0);
+    assert_eq!(s.client.nonce(&attendee_id), 1);
+}
+
+#[test]
+#[should_panic(expected = "incorrect nonce")]
+fn test_claim_for_rejects_a_replayed_signature() {
+    let env = Env::default();
+    let s = setup(&env);
+    let (attendee_id, signer) = signing_attendee(&s);
+    let relayer = env.accounts().generate();
+
+    let sig = sign(
+        &env,
+        &signer,
+        &s.contract,
+        symbol!("claim_for"),
+        (&attendee_id, 0i128),
+    );
+    s.client.with_source_account(&relayer).claim_for(&sig, &0);
+    s.client.with_source_account(&relayer).claim_for(&sig, &0);
+}
+
+#[test]
+fn test_claim_for_rejects_another_identitys_signature() {
+    let env = Env::default();
+    let s = setup(&env);
+    let (attendee_id, signer) = signing_attendee(&s);
+    let (_, thief) = ed25519::generate(&env);
+    let relayer = env.accounts().generate();
+    let relay = s.client.with_source_account(&relayer);
+
+    // the nonce and the claim are the attendee's, only the key is wrong
+    let forged = sign(
+        &env,
+        &thief,
+        &s.contract,
+        symbol!("claim_for"),
+        (&attendee_id, 0i128),
+    );
+    assert!(relay
+        .try_claim_for(&forge(forged, &attendee_id), &0)
+        .is_err());
+    assert_eq!(s.token.balance(&attendee_id), 0);
+    assert_eq!(s.client.nonce(&attendee_id), 0);
+
+    // nothing was used up, the attendee's own signature still goes through
+    let sig = sign(
+        &env,
+        &signer,
+        &s.contract,
+        symbol!("claim_for"),
+        (&attendee_id, 0i128),
+    );
+    relay.claim_for(&sig, &0);
+    assert_eq!(s.token.balance(&attendee_id), DEPOSIT_FEE);
+}
+
+#[test]
+fn test_deposit_batch_registers_signed_permits() {
+    let env = Env::default();
+    let s = setup_with(
+        &env,
+        Init {
+            features: FEATURE_BATCH_DEPOSITS,
+            ..default_init(&env)
+        },
+    );
+    let (depositor_id, signer) = signing_depositor(&s);
+
+    s.client
+        .with_source_account(&s.admin)
+        .deposit_batch(&vec![&env, deposit_permit(&s, &signer, 0)]);
+
+    assert_eq!(s.token.balance(&depositor_id), 0);
+    assert_eq!(s.token.balance(&s.contract_id), DEPOSIT_FEE);
+    assert_eq!(s.client.required_allowance(&depositor_id), 0);
+    assert_eq!(s.client.nonce(&depositor_id), 1);
+}
+
+#[test]
+#[should_panic(expected = "incorrect nonce")]
+fn test_deposit_batch_rejects_a_replayed_permit() {
+    let env = Env::default();
+    let s = setup_with(
+        &env,
+        Init {
+            features: FEATURE_BATCH_DEPOSITS,
+            ..default_init(&env)
+        },
+    );
+    let (_, signer) = signing_depositor(&s);
+    let permit = deposit_permit(&s, &signer, 0);
+    let admin = s.client.with_source_account(&s.admin);
+
+    admin.deposit_batch(&vec![&env, permit.clone()]);
+    admin.deposit_batch(&vec![&env, permit]);
+}
+
+#[test]
+fn test_deposit_batch_rejects_another_identitys_permit() {
+    let env = Env::default();
+    let s = setup_with(
+        &env,
+        Init {
+            features: FEATURE_BATCH_DEPOSITS,
+            ..default_init(&env)
+        },
+    );
+    let (depositor_id, signer) = signing_depositor(&s);
+    let (_, thief) = ed25519::generate(&env);
+    let admin = s.client.with_source_account(&s.admin);
+
+    // a permit signed by another key can't spend the depositor's allowance
+    let mut forged = deposit_permit(&s, &thief, 0);
+    forged.signature = forge(forged.signature, &depositor_id);
+    assert!(admin.try_deposit_batch(&vec![&env, forged]).is_err());
+    assert_eq!(s.token.balance(&s.contract_id), 0);
+    assert_eq!(s.client.nonce(&depositor_id), 0);
+
+    // the depositor's own permit for the same nonce still goes through
+    admin.deposit_batch(&vec![&env, deposit_permit(&s, &signer, 0)]);
+    assert_eq!(s.token.balance(&s.contract_id), DEPOSIT_FEE);
+}
+
+#[test]
+#[cfg(feature = "checks")]
+#[should_panic(expected = "invariant violated: deposits don't add up to the pool")]
+fn test_invariants_catch_untracked_deposits() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+
+    deposit(&s, &user);
+    // pretend less was deposited than the registrations were credited
+    env.as_contract(&s.contract, || {
+        env.storage().set(DataKey::TotalDeposited, DEPOSIT_FEE / 2);
+    });
+    s.client
+        .with_source_account(&s.admin)
+        .block(&Identifier::Account(env.accounts().generate()));
+}
+
+#[test]
+#[cfg(feature = "checks")]
+#[should_panic(expected = "invariant violated: distribution is locked but the pool isn't settled")]
+fn test_invariants_catch_lock_without_settlement() {
+    let env = Env::default();
+    let s = setup(&env);
+
+    env.as_contract(&s.contract, || {
+        env.storage().set(DataKey::Locked, true);
+    });
+    s.client
+        .with_source_account(&s.admin)
+        .block(&Identifier::Account(env.accounts().generate()));
+}
+
+#[test]
+fn test_clawback_is_reported_as_a_shortfall() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+
+    deposit(&s, &user);
+    s.token.with_source_account(&s.admin).clawback(
+        &Signature::Invoker,
+        &0,
+        &s.contract_id,
+        &(DEPOSIT_FEE / 4),
+    );
+
+    // the contract keeps working with less than it tracks
+    s.client
+        .with_source_account(&s.admin)
+        .block(&Identifier::Account(env.accounts().generate()));
+    assert_eq!(s.client.shortfall(), DEPOSIT_FEE / 4);
+    assert_eq!(s.client.reconcile(), 0);
+
+    let events = contract_events(&s, symbol!("shortfall"));
+    assert_eq!(events.len(), 1);
+    let missing: i128 = events[0].1.try_into_val(&env).unwrap();
+    assert_eq!(missing, DEPOSIT_FEE / 4);
+}
+
+#[test]
+fn test_reserve_absorbs_a_clawback_before_the_pool() {
+    let env = Env::default();
+    let s = setup_with(
+        &env,
+        Init {
+            reserve_bps: 1000,
+            ..default_init(&env)
+        },
+    );
+    let user = funded_user(&s);
+    let user_id = Identifier::Account(user.clone());
+    let other = funded_user(&s);
+    let clawback = |amount: i128| {
+        s.token.with_source_account(&s.admin).clawback(
+            &Signature::Invoker,
+            &0,
+            &s.contract_id,
+            &amount,
+        )
+    };
+
+    deposit(&s, &user);
+    deposit(&s, &other);
+    assert_eq!(s.client.reserve(), DEPOSIT_FEE / 5);
+
+    // the reserve covers what it can, the rest comes out of the pool
+    clawback(DEPOSIT_FEE / 10);
+    assert_eq!(s.client.reserve(), DEPOSIT_FEE / 10);
+    clawback(DEPOSIT_FEE / 5);
+    assert_eq!(s.client.reserve(), 0);
+    assert_eq!(s.client.shortfall(), 3 * DEPOSIT_FEE / 10);
+
+    s.client
+        .with_source_account(&s.admin)
+        .check_in_many(&vec![&env, user_id.clone()]);
+    s.client.with_source_account(&user).distribute();
+    assert_eq!(
+        s.token.balance(&user_id),
+        2 * DEPOSIT_FEE - 3 * DEPOSIT_FEE / 10
+    );
+}
+
+#[test]
+fn test_reserve_is_released_into_the_payout() {
+    let env = Env::default();
+    let s = setup_with(
+        &env,
+        Init {
+            reserve_bps: 1000,
+            ..default_init(&env)
+        },
+    );
+    let user = funded_user(&s);
+    let user_id = Identifier::Account(user.clone());
+
+    deposit(&s, &user);
+    s.client
+        .with_source_account(&s.admin)
+        .check_in_many(&vec![&env, user_id.clone()]);
+    s.client.with_source_account(&user).distribute();
+    assert_eq!(s.token.balance(&user_id), DEPOSIT_FEE);
+    assert_eq!(s.client.reserve(), 0);
+}
+
+#[test]
+#[should_panic(expected = "nothing can be released while the pool is below its minimum")]
+fn test_release_waits_for_the_minimum_pool() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+    let admin = s.client.with_source_account(&s.admin);
+
+    admin.set_min_pool(&(2 * DEPOSIT_FEE));
+    admin.set_release_schedule(&vec![
+        &env,
+        Checkpoint {
+            milestone: Milestone::RegistrationClose,
+            bps: 5000,
+        },
+    ]);
+    deposit(&s, &user);
+    admin.release();
+}
+
+#[test]
+fn test_release_accumulates_checkpoints_and_leaves_the_payout() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+    let user_id = Identifier::Account(user.clone());
+    let admin_id = Identifier::Account(s.admin.clone());
+    let admin = s.client.with_source_account(&s.admin);
+
+    admin.set_release_schedule(&vec![
+        &env,
+        Checkpoint {
+            milestone: Milestone::Meetup,
+            bps: 2500,
+        },
+        Checkpoint {
+            milestone: Milestone::EmergencyVote,
+            bps: 2500,
+        },
+    ]);
+    deposit(&s, &user);
+    deposit(&s, &funded_user(&s));
+
+    assert_eq!(admin.release(), DEPOSIT_FEE / 2);
+    // a checkpoint is only released once
+    assert_eq!(admin.release(), 0);
+
+    env.ledger()
+        .with_mut(|ledger| ledger.timestamp += EMERGENCY_DELAY);
+    assert_eq!(admin.release(), DEPOSIT_FEE / 2);
+    assert_eq!(s.token.balance(&admin_id), DEPOSIT_FEE);
+
+    // the attendees share what's left after the releases
+    admin.check_in_many(&vec![&env, user_id.clone()]);
+    s.client.with_source_account(&user).distribute();
+    assert_eq!(s.token.balance(&user_id), DEPOSIT_FEE);
+}
+
+#[test]
+fn test_extra_is_tracked_apart_and_boosts_the_pool() {
+    let env = Env::default();
+    let s = setup(&env);
+    let sponsor = env.accounts().generate();
+    let sponsor_id = Identifier::Account(sponsor.clone());
+    let other = funded_user(&s);
+    let extra = DEPOSIT_FEE / 2;
+
+    s.token.with_source_account(&s.admin).mint(
+        &Signature::Invoker,
+        &0,
+        &sponsor_id,
+        &(DEPOSIT_FEE + extra),
+    );
+    s.token.with_source_account(&sponsor).approve(
+        &Signature::Invoker,
+        &0,
+        &s.contract_id,
+        &(DEPOSIT_FEE + extra),
+    );
+    let registration = s
+        .client
+        .with_source_account(&sponsor)
+        .deposit_with_extra(&extra, &None, &None);
+    assert_eq!(
+        (registration.amount, registration.extra),
+        (DEPOSIT_FEE, extra)
+    );
+    deposit(&s, &other);
+
+    // the extra doesn't weigh in, both attendees split it evenly
+    s.client.with_source_account(&s.admin).check_in_many(&vec![
+        &env,
+        sponsor_id.clone(),
+        Identifier::Account(other.clone()),
+    ]);
+    s.client.with_source_account(&sponsor).distribute();
+    assert_eq!(s.token.balance(&sponsor_id), DEPOSIT_FEE + extra / 2);
+}
+
+#[test]
+fn test_refund_keeps_the_sponsor_contribution() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = env.accounts().generate();
+    let user_id = Identifier::Account(user.clone());
+    let extra = DEPOSIT_FEE / 2;
+
+    s.client
+        .with_source_account(&s.admin)
+        .set_min_pool(&(2 * DEPOSIT_FEE));
+    s.token.with_source_account(&s.admin).mint(
+        &Signature::Invoker,
+        &0,
+        &user_id,
+        &(DEPOSIT_FEE + extra),
+    );
+    s.token.with_source_account(&user).approve(
+        &Signature::Invoker,
+        &0,
+        &s.contract_id,
+        &(DEPOSIT_FEE + extra),
+    );
+    s.client
+        .with_source_account(&user)
+        .deposit_with_extra(&extra, &None, &None);
+
+    // registration closes short of the minimum, so the event is cancelled
+    env.ledger().with_mut(|ledger| ledger.timestamp += 1);
+    let rows = s.client.preview_cancellation(&0);
+    assert_eq!(rows.get_unchecked(0).unwrap().refund, DEPOSIT_FEE);
+    assert_eq!(s.client.with_source_account(&user).refund(), DEPOSIT_FEE);
+    assert_eq!(s.token.balance(&user_id), DEPOSIT_FEE);
+    assert_eq!(s.token.balance(&s.contract_id), extra);
+}
+
+#[test]
+fn test_treasury_pays_out_up_to_what_it_holds() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+    let user_id = Identifier::Account(user.clone());
+
+    deposit(&s, &user);
+    env.as_contract(&s.contract, || {
+        treasury_transfer(&env, Direction::Out(Bucket::Pool), &user_id, &DEPOSIT_FEE);
+    });
+    assert_eq!(s.token.balance(&s.contract_id), 0);
+    assert_eq!(s.token.balance(&user_id), DEPOSIT_FEE);
+}
+
+#[test]
+#[should_panic(expected = "Status(ContractError(12))")]
+fn test_treasury_refuses_to_pay_more_than_it_holds() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+
+    deposit(&s, &user);
+    env.as_contract(&s.contract, || {
+        let amount = DEPOSIT_FEE + 1;
+        let user_id = Identifier::Account(user.clone());
+        treasury_transfer(&env, Direction::Out(Bucket::Pool), &user_id, &amount);
+    });
+}
+
+#[test]
+#[should_panic(expected = "Status(ContractError(12))")]
+fn test_treasury_keeps_the_pool_out_of_the_lost_and_found() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+
+    deposit(&s, &user);
+    s.token
+        .with_source_account(&s.admin)
+        .mint(&Signature::Invoker, &0, &s.contract_id, &5);
+    s.client
+        .with_source_account(&s.admin)
+        .set_strict_accounting(&true);
+    s.client.reconcile();
+    // the contract holds enough, but the extra 5 are parked
+    env.as_contract(&s.contract, || {
+        let amount = DEPOSIT_FEE + 1;
+        let user_id = Identifier::Account(user.clone());
+        treasury_transfer(&env, Direction::Out(Bucket::Pool), &user_id, &amount);
+    });
+}
+
+#[test]
+#[should_panic(expected = "Status(ContractError(12))")]
+fn test_treasury_pays_the_lost_and_found_only_what_is_parked() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+
+    deposit(&s, &user);
+    env.as_contract(&s.contract, || {
+        let user_id = Identifier::Account(user.clone());
+        treasury_transfer(&env, Direction::Out(Bucket::LostAndFound), &user_id, &1);
+    });
+}
+
+#[test]
+#[should_panic(expected = "deposits can only be routed by the payments router")]
+fn test_routed_deposit_requires_the_router() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+
+    s.client
+        .with_source_account(&s.admin)
+        .set_payments_router(&BytesN::from_array(&env, &[7; 32]));
+    // an account can't pose as the router to register somebody else
+    s.client
+        .with_source_account(&user)
+        .on_deposit(&Identifier::Account(env.accounts().generate()), &DEPOSIT_FEE);
+}
+
+#[test]
+fn test_strict_reconcile_parks_direct_transfers() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+    let user_id = Identifier::Account(user.clone());
+
+    deposit(&s, &user);
+    // tokens sent without calling deposit
+    s.token
+        .with_source_account(&s.admin)
+        .mint(&Signature::Invoker, &0, &s.contract_id, &5);
+    s.client
+        .with_source_account(&s.admin)
+        .set_strict_accounting(&true);
+
+    assert_eq!(s.client.reconcile(), 5);
+    assert_eq!(s.client.reconcile(), 0);
+    assert_eq!(s.client.lost_and_found(), 5);
+
+    s.client
+        .with_source_account(&s.admin)
+        .return_lost(&user_id, &5);
+    assert_eq!(s.client.lost_and_found(), 0);
+    assert_eq!(s.token.balance(&user_id), 5);
+}
+
+#[test]
+#[should_panic(expected = "funds can only be swept to a declared destination")]
+fn test_close_only_sweeps_to_declared_destinations() {
+    let env = Env::default();
+    let s = setup(&env);
+    let treasury = Identifier::Account(env.accounts().generate());
+    let user = funded_user(&s);
+    let admin = s.client.with_source_account(&s.admin);
+
+    admin.set_sweep_destinations(&vec![&env, treasury]);
+    deposit(&s, &user);
+    admin.check_in_many(&vec![&env, Identifier::Account(user.clone())]);
+    s.client.with_source_account(&user).distribute();
+    // the admin itself isn't on the list
+    admin.close(&Identifier::Account(s.admin.clone()));
+}
+
+#[test]
+#[should_panic(expected = "the lost and found has to be returned first")]
+fn test_close_leaves_the_lost_and_found_alone() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+    let admin = s.client.with_source_account(&s.admin);
+
+    deposit(&s, &user);
+    s.token
+        .with_source_account(&s.admin)
+        .mint(&Signature::Invoker, &0, &s.contract_id, &5);
+    admin.set_strict_accounting(&true);
+    s.client.reconcile();
+    admin.check_in_many(&vec![&env, Identifier::Account(user.clone())]);
+    s.client.with_source_account(&user).distribute();
+
+    admin.close(&Identifier::Account(s.admin.clone()));
+}
+
+#[test]
+fn test_close_sweeps_within_the_outflow_cap() {
+    let env = Env::default();
+    let s = setup(&env);
+    let treasury = Identifier::Account(env.accounts().generate());
+    let user = funded_user(&s);
+    let admin = s.client.with_source_account(&s.admin);
+
+    admin.set_outflow_cap(&DEPOSIT_FEE);
+    admin.set_sweep_destinations(&vec![&env, treasury.clone()]);
+    deposit(&s, &user);
+    admin.check_in_many(&vec![&env, Identifier::Account(user.clone())]);
+    // the claim spends this ledger's cap
+    s.client.with_source_account(&user).distribute();
+    s.token
+        .with_source_account(&s.admin)
+        .mint(&Signature::Invoker, &0, &s.contract_id, &5);
+
+    admin.close(&treasury);
+    assert_eq!(s.token.balance(&treasury), 0);
+    assert_eq!(s.client.outflow_left(), Some(0));
+
+    env.ledger().with_mut(|ledger| ledger.sequence_number += 1);
+    admin.close(&treasury);
+    assert_eq!(s.token.balance(&treasury), 5);
+    assert_eq!(s.token.balance(&s.contract_id), 0);
+}
+
+#[test]
+fn test_operator_claims_for_the_attendee() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+    let user_id = Identifier::Account(user.clone());
+    let operator = env.accounts().generate();
+
+    deposit(&s, &user);
+    s.client
+        .with_source_account(&s.admin)
+        .check_in_many(&vec![&env, user_id.clone()]);
+    s.client
+        .with_source_account(&user)
+        .approve_operator(&Identifier::Account(operator.clone()));
+
+    s.client
+        .with_source_account(&operator)
+        .claim_as_operator(&user_id);
+    // the share goes to the attendee, never to the operator
+    assert_eq!(s.token.balance(&user_id), DEPOSIT_FEE);
+    assert_eq!(s.token.balance(&Identifier::Account(operator)), 0);
+}
+
+#[test]
+#[should_panic(expected = "this address isn't an approved operator for the attendee")]
+fn test_revoked_operator_cannot_claim() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+    let user_id = Identifier::Account(user.clone());
+    let operator = env.accounts().generate();
+
+    deposit(&s, &user);
+    s.client
+        .with_source_account(&s.admin)
+        .check_in_many(&vec![&env, user_id.clone()]);
+    s.client
+        .with_source_account(&user)
+        .approve_operator(&Identifier::Account(operator.clone()));
+    s.client.with_source_account(&user).revoke_operator();
+
+    s.client
+        .with_source_account(&operator)
+        .claim_as_operator(&user_id);
+}
+
+// A meetup a week out, postponed by another week right away
+fn setup_postponed(env: &Env) -> Setup {
+    env.ledger().with_mut(|ledger| ledger.timestamp = 7 * 86400);
+    let s = setup(env);
+    env.ledger().with_mut(|ledger| ledger.timestamp = 0);
+    s.client
+        .with_source_account(&s.admin)
+        .postpone(&(14 * 86400));
+    s
+}
+
+#[test]
+#[should_panic(expected = "Status(ContractError(13))")]
+fn test_cancellation_waits_for_the_cooldown() {
+    let env = Env::default();
+    let s = setup_postponed(&env);
+    let user = funded_user(&s);
+
+    deposit(&s, &user);
+    env.ledger().with_mut(|ledger| ledger.timestamp += CANCEL_COOLDOWN - 1);
+    s.client.with_source_account(&user).cancel_registration();
+}
+
+#[test]
+#[should_panic(expected = "Status(ContractError(14))")]
+fn test_cancellations_are_capped_per_address() {
+    let env = Env::default();
+    let s = setup_postponed(&env);
+    let user = funded_user(&s);
+    let user_id = Identifier::Account(user.clone());
+
+    for _ in 0..3 {
+        testutils::approve_required_allowance(&env, &s.contract, &s.token_id, &user);
+        deposit(&s, &user);
+        env.ledger().with_mut(|ledger| ledger.timestamp += CANCEL_COOLDOWN);
+        s.client.with_source_account(&user).cancel_registration();
+        assert_eq!(s.token.balance(&user_id), DEPOSIT_FEE);
+    }
+}
+
+#[test]
+fn test_cancel_penalty_withholds_part_of_the_deposit() {
+    let env = Env::default();
+    let s = setup_postponed(&env);
+    let user = funded_user(&s);
+    let user_id = Identifier::Account(user.clone());
+
+    // no cancelling for an hour, then the penalty decays to zero over the next
+    s.client
+        .with_source_account(&s.admin)
+        .set_cancel_penalty(&PiecewiseSchedule {
+            points: vec![
+                &env,
+                Breakpoint {
+                    at: 3600,
+                    bps: 10000,
+                },
+                Breakpoint { at: 7200, bps: 0 },
+            ],
+        });
+    deposit(&s, &user);
+    env.ledger().with_mut(|ledger| ledger.timestamp += 3600);
+    assert!(s
+        .client
+        .with_source_account(&user)
+        .try_cancel_registration()
+        .is_err());
+
+    env.ledger().with_mut(|ledger| ledger.timestamp += 1800);
+    s.client.with_source_account(&user).cancel_registration();
+    assert_eq!(s.token.balance(&user_id), DEPOSIT_FEE / 2);
+    // the withheld half stays in the pool
+    assert_eq!(s.token.balance(&s.contract_id), DEPOSIT_FEE / 2);
+}
+
+#[test]
+fn test_late_check_in_counts_for_less() {
+    let env = Env::default();
+    let s = setup(&env);
+    let early = funded_user(&s);
+    let late = funded_user(&s);
+    let early_id = Identifier::Account(early.clone());
+    let late_id = Identifier::Account(late.clone());
+    let admin = s.client.with_source_account(&s.admin);
+
+    // full weight at the start, half of it half an hour in
+    admin.set_check_in_weights(&PiecewiseSchedule {
+        points: vec![
+            &env,
+            Breakpoint { at: 0, bps: 10000 },
+            Breakpoint {
+                at: 1800,
+                bps: 5000,
+            },
+        ],
+    });
+    deposit(&s, &early);
+    deposit(&s, &late);
+    admin.check_in_many(&vec![&env, early_id.clone()]);
+    env.ledger().with_mut(|ledger| ledger.timestamp += 1800);
+    admin.check_in_many(&vec![&env, late_id.clone()]);
+
+    // weights of 2:1 split the pool of two deposits
+    let pool = 2 * DEPOSIT_FEE;
+    assert_eq!(s.client.simulate_claim(&early_id, &0).share, pool * 2 / 3);
+    assert_eq!(s.client.simulate_claim(&late_id, &0).share, pool / 3);
+}
+
+#[test]
+fn test_tier_seats_run_down_within_a_ledger() {
+    let env = Env::default();
+    let s = setup_with(
+        &env,
+        Init {
+            features: FEATURE_TIERS,
+            tier_capacities: vec![&env, 2, 1],
+            ..default_init(&env)
+        },
+    );
+
+    // every registration below lands in the same ledger
+    deposit_in_tier(&s, &funded_user(&s), 0);
+    deposit_in_tier(&s, &funded_user(&s), 1);
+    deposit_in_tier(&s, &funded_user(&s), 0);
+
+    assert_eq!(s.client.seats_left(&0), 0);
+    assert_eq!(s.client.seats_left(&1), 0);
+}
+
+#[test]
+#[should_panic(expected = "Status(ContractError(7))")]
+fn test_last_seat_goes_to_first_registration_in_ledger() {
+    let env = Env::default();
+    let s = setup_with(
+        &env,
+        Init {
+            features: FEATURE_TIERS,
+            tier_capacities: vec![&env, 1],
+            ..default_init(&env)
+        },
+    );
+    let first = funded_user(&s);
+    let second = funded_user(&s);
+
+    deposit_in_tier(&s, &first, 0);
+    // racing for the same, now sold out, seat
+    deposit_in_tier(&s, &second, 0);
+}
+
+#[test]
+#[should_panic(expected = "only checked-in attendees can contribute to projects")]
+fn test_round_contributions_require_check_in() {
+    let env = Env::default();
+    let s = setup_with(
+        &env,
+        Init {
+            features: FEATURE_ROUNDS,
+            ..default_init(&env)
+        },
+    );
+    let user = funded_user(&s);
+
+    s.client
+        .with_source_account(&s.admin)
+        .add_project(&Identifier::Account(env.accounts().generate()));
+    deposit(&s, &user);
+    s.client.with_source_account(&user).contribute(&0, &DEPOSIT_FEE);
+}
+
+#[test]
+#[should_panic(expected = "voting opens after the meetup")]
+fn test_forfeit_vote_waits_for_the_meetup() {
+    let env = Env::default();
+    let s = setup_with(
+        &env,
+        Init {
+            features: FEATURE_FORFEIT_VOTE,
+            ..default_init(&env)
+        },
+    );
+    let user = funded_user(&s);
+
+    s.client
+        .with_source_account(&s.admin)
+        .add_candidate(&Identifier::Account(env.accounts().generate()));
+    deposit(&s, &user);
+    s.client
+        .with_source_account(&s.admin)
+        .check_in_many(&vec![&env, Identifier::Account(user.clone())]);
+    s.client.with_source_account(&user).vote(&0);
+}
+
+#[test]
+#[should_panic(expected = "Status(ContractError(10))")]
+fn test_check_in_many_rejects_oversized_batch() {
+    let env = Env::default();
+    let s = setup(&env);
+    let limit = s.client.limits().max_check_ins_per_call;
+
+    let mut attendees = Vec::new(&env);
+    for _ in 0..=limit {
+        attendees.push_back(Identifier::Account(env.accounts().generate()));
+    }
+    s.client.with_source_account(&s.admin).check_in_many(&attendees);
+}
+
+// What someone claiming `original`'s voucher for `recipient` commits to
+fn voucher_commitment(env: &Env, secret: &Bytes, recipient: &AccountId) -> BytesN<32> {
+    let mut preimage = secret.clone();
+    preimage.append(&Identifier::Account(recipient.clone()).serialize(env));
+    env.crypto().sha256(&preimage)
+}
+
+fn voucher_setup(s: &Setup, secret: &Bytes) -> Identifier {
+    let user = funded_user(s);
+    let user_id = Identifier::Account(user.clone());
+    let admin = s.client.with_source_account(&s.admin);
+
+    deposit(s, &user);
+    admin.check_in_many(&vec![s.env, user_id.clone()]);
+    admin.issue_voucher(&user_id, &s.env.crypto().sha256(secret));
+    admin.mark_unused(&user_id);
+    user_id
+}
+
+#[test]
+fn test_voucher_claim_pays_the_committed_recipient() {
+    let env = Env::default();
+    let s = setup(&env);
+    let secret = Bytes::from_slice(&env, b"lost key voucher");
+    let user = voucher_setup(&s, &secret);
+    let new_key = env.accounts().generate();
+
+    s.client.commit_voucher_claim(&voucher_commitment(&env, &secret, &new_key));
+    env.ledger().with_mut(|ledger| ledger.sequence_number += 1);
+    s.client
+        .with_source_account(&new_key)
+        .claim_with_voucher(&user, &secret);
+    assert_eq!(s.token.balance(&Identifier::Account(new_key)), DEPOSIT_FEE);
+}
+
+#[test]
+#[should_panic(expected = "commit to the voucher claim first")]
+fn test_voucher_secret_seen_in_flight_is_useless() {
+    let env = Env::default();
+    let s = setup(&env);
+    let secret = Bytes::from_slice(&env, b"lost key voucher");
+    let user = voucher_setup(&s, &secret);
+    let new_key = env.accounts().generate();
+    let front_runner = env.accounts().generate();
+
+    s.client.commit_voucher_claim(&voucher_commitment(&env, &secret, &new_key));
+    env.ledger().with_mut(|ledger| ledger.sequence_number += 1);
+    s.client
+        .with_source_account(&front_runner)
+        .claim_with_voucher(&user, &secret);
+}
+
+#[test]
+#[should_panic(expected = "has to be committed in an earlier ledger")]
+fn test_voucher_commitment_needs_a_ledger_to_settle() {
+    let env = Env::default();
+    let s = setup(&env);
+    let secret = Bytes::from_slice(&env, b"lost key voucher");
+    let user = voucher_setup(&s, &secret);
+    let front_runner = env.accounts().generate();
+
+    // committing and revealing in the same ledger as the honest claim
+    s.client.commit_voucher_claim(&voucher_commitment(&env, &secret, &front_runner));
+    s.client
+        .with_source_account(&front_runner)
+        .claim_with_voucher(&user, &secret);
+}
+
+#[test]
+#[should_panic(expected = "the voucher secret doesn't match")]
+fn test_voucher_claim_checks_the_secret() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = Identifier::Account(funded_user(&s));
+    let secret = Bytes::from_slice(&env, b"lost key voucher");
+
+    s.client
+        .with_source_account(&s.admin)
+        .issue_voucher(&user, &env.crypto().sha256(&secret));
+    s.client.with_source_account(&s.admin).mark_unused(&user);
+    s.client
+        .with_source_account(&env.accounts().generate())
+        .claim_with_voucher(&user, &Bytes::from_slice(&env, b"a guess"));
+}
+
+#[test]
+fn test_time_until_counts_down_from_ledger_time() {
+    let env = Env::default();
+    let s = setup(&env);
+
+    // the fixture's meetup is right now
+    assert_eq!(s.client.time_until(&Milestone::Meetup), 0);
+    assert_eq!(
+        s.client.time_until(&Milestone::EmergencyVote),
+        EMERGENCY_DELAY
+    );
+    assert_eq!(s.client.time_until(&Milestone::ClaimsEnd), CLOSE_GRACE);
+    assert_eq!(s.client.time_until(&Milestone::SweepUnlock), CLOSE_GRACE);
+
+    // a longer fee vesting holds the sweep back
+    s.client
+        .with_source_account(&s.admin)
+        .set_fee_vesting(&(2 * CLOSE_GRACE));
+    assert_eq!(
+        s.client.time_until(&Milestone::SweepUnlock),
+        2 * CLOSE_GRACE
+    );
+}
+
+#[test]
+fn test_permissions_follow_roles() {
+    let env = Env::default();
+    let s = setup(&env);
+    let admin = Identifier::Account(s.admin.clone());
+    let registered = funded_user(&s);
+    let registered_id = Identifier::Account(registered.clone());
+    let user = Identifier::Account(funded_user(&s));
+
+    assert_eq!(s.client.permissions_of(&user), ACTION_DEPOSIT);
+    assert_eq!(
+        s.client.permissions_of(&admin),
+        ACTION_DEPOSIT | ACTION_CHECK_IN
+    );
+    // the fee is fixed, so it's paid once
+    deposit(&s, &registered);
+    assert_eq!(s.client.permissions_of(&registered_id), 0);
+
+    s.client.with_source_account(&s.admin).block(&user);
+    assert_eq!(s.client.permissions_of(&user), 0);
+}
+
+#[test]
+fn test_registration_extends_until_the_bound() {
+    let env = Env::default();
+    let s = setup(&env);
+
+    s.client
+        .with_source_account(&s.admin)
+        .set_auto_extension(&AutoExtension {
+            min_attendees: 2,
+            increment: 3600,
+            max_extensions: 2,
+        });
+    env.ledger().with_mut(|ledger| ledger.timestamp += 5000);
+    // two increments came due, after that the bound holds the date
+    assert_eq!(s.client.extend_registration(), 7200);
+    env.ledger().with_mut(|ledger| ledger.timestamp += 5000);
+    assert_eq!(s.client.extend_registration(), 7200);
+}
+
+#[test]
+fn test_emergency_quorum_lets_depositors_withdraw() {
+    let env = Env::default();
+    let s = setup_with(
+        &env,
+        Init {
+            features: FEATURE_EMERGENCY_WITHDRAWAL,
+            ..default_init(&env)
+        },
+    );
+    let user = funded_user(&s);
+    let other = funded_user(&s);
+
+    deposit(&s, &user);
+    deposit(&s, &other);
+    env.ledger().with_mut(|ledger| ledger.timestamp += EMERGENCY_DELAY);
+    s.client.with_source_account(&user).vote_emergency();
+    s.client.with_source_account(&other).vote_emergency();
+
+    s.client.with_source_account(&user).emergency_withdraw();
+    s.client.with_source_account(&other).emergency_withdraw();
+    assert_eq!(s.token.balance(&Identifier::Account(user)), DEPOSIT_FEE);
+    assert_eq!(s.token.balance(&Identifier::Account(other)), DEPOSIT_FEE);
+    assert_eq!(s.token.balance(&s.contract_id), 0);
+}
+
+#[test]
+#[should_panic(expected = "no emergency withdrawal was triggered")]
+fn test_emergency_withdrawal_waits_for_the_quorum() {
+    let env = Env::default();
+    let s = setup_with(
+        &env,
+        Init {
+            features: FEATURE_EMERGENCY_WITHDRAWAL,
+            ..default_init(&env)
+        },
+    );
+    let user = funded_user(&s);
+    let other = funded_user(&s);
+
+    deposit(&s, &user);
+    deposit(&s, &other);
+    env.ledger().with_mut(|ledger| ledger.timestamp += EMERGENCY_DELAY);
+    s.client.with_source_account(&user).vote_emergency();
+    s.client.with_source_account(&user).emergency_withdraw();
+}
+
+#[test]
+#[should_panic(expected = "an emergency withdrawal was already triggered")]
+fn test_emergency_votes_close_once_triggered() {
+    let env = Env::default();
+    let s = setup_with(
+        &env,
+        Init {
+            features: FEATURE_EMERGENCY_WITHDRAWAL,
+            ..default_init(&env)
+        },
+    );
+    let users = [funded_user(&s), funded_user(&s), funded_user(&s), funded_user(&s)];
+    for user in users.iter() {
+        deposit(&s, user);
+    }
+    env.ledger().with_mut(|ledger| ledger.timestamp += EMERGENCY_DELAY);
+    // three out of four deposits reach the 75% quorum
+    for user in users.iter() {
+        s.client.with_source_account(user).vote_emergency();
+    }
+}
+
+#[test]
+#[should_panic(expected = "the emergency deadline hasn't passed yet")]
+fn test_emergency_vote_waits_for_the_deadline() {
+    let env = Env::default();
+    let s = setup_with(
+        &env,
+        Init {
+            features: FEATURE_EMERGENCY_WITHDRAWAL,
+            ..default_init(&env)
+        },
+    );
+    let user = funded_user(&s);
+
+    deposit(&s, &user);
+    env.ledger().with_mut(|ledger| ledger.timestamp += 1);
+    s.client.with_source_account(&user).vote_emergency();
+}
+
+#[test]
+fn test_any_call_applies_due_extensions() {
+    let env = Env::default();
+    let s = setup(&env);
+
+    s.client
+        .with_source_account(&s.admin)
+        .set_auto_extension(&AutoExtension {
+            min_attendees: 2,
+            increment: 3600,
+            max_extensions: 1,
+        });
+    env.ledger().with_mut(|ledger| ledger.timestamp += 100);
+    // views see the extension without storing it
+    assert_eq!(s.client.get_info().meetup_date, 3600);
+    env.as_contract(&s.contract, || {
+        let stored: u64 = env.storage().get_unchecked(DataKey::MeetupDate).unwrap();
+        assert_eq!(stored, 0);
+    });
+
+    s.client.poke();
+    env.as_contract(&s.contract, || {
+        let stored: u64 = env.storage().get_unchecked(DataKey::MeetupDate).unwrap();
+        assert_eq!(stored, 3600);
+    });
+}
+
+#[test]
+fn test_latest_announcement_replaces_the_previous_one() {
+    let env = Env::default();
+    let s = setup(&env);
+    let admin = s.client.with_source_account(&s.admin);
+
+    assert!(s.client.latest_announcement().is_none());
+    admin.announce(&BytesN::from_array(&env, &[1; 32]), &Bytes::from_slice(&env, b"ipfs://a"));
+    admin.announce(&BytesN::from_array(&env, &[2; 32]), &Bytes::from_slice(&env, b"ipfs://b"));
+
+    let announcement = s.client.latest_announcement().unwrap();
+    assert_eq!(announcement.message_hash, BytesN::from_array(&env, &[2; 32]));
+    assert_eq!(announcement.uri, Bytes::from_slice(&env, b"ipfs://b"));
+}
+
+#[test]
+fn test_info_carries_the_utc_offset() {
+    let env = Env::default();
+    let s = setup(&env);
+
+    let info = s.client.get_info();
+    assert_eq!(info.utc_offset, UTC_OFFSET);
+    assert_eq!(info.meetup_date, env.ledger().timestamp());
+}
+
+#[test]
+#[should_panic(expected = "this contract was closed")]
+fn test_closed_contract_rejects_calls() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+
+    deposit(&s, &user);
+    s.client
+        .with_source_account(&s.admin)
+        .check_in_many(&vec![&env, Identifier::Account(user.clone())]);
+    s.client.with_source_account(&user).distribute();
+    s.client
+        .with_source_account(&s.admin)
+        .close(&Identifier::Account(s.admin.clone()));
+
+    s.client.features();
+}
+
+#[test]
+#[should_panic(expected = "attendance comes from the oracle, use finalize_attendance")]
+fn test_oracle_replaces_staff_check_ins() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+    let oracle = BytesN::from_array(&env, &[7; 32]);
+
+    deposit(&s, &user);
+    s.client.with_source_account(&s.admin).set_attendance_oracle(&oracle);
+    s.client
+        .with_source_account(&s.admin)
+        .check_in_many(&vec![&env, Identifier::Account(user.clone())]);
+}
+
+#[test]
+fn test_flexible_deposit_can_be_topped_up() {
+    let env = Env::default();
+    let s = setup_with(
+        &env,
+        Init {
+            deposit_limits: DepositLimits {
+                flexible: true,
+                min_deposit: DEPOSIT_FEE,
+                max_deposit: 3 * DEPOSIT_FEE,
+                pool_cap: 0,
+            },
+            ..default_init(&env)
+        },
+    );
+    let user = funded_user(&s);
+    s.token.with_source_account(&s.admin).mint(
+        &Signature::Invoker,
+        &0,
+        &Identifier::Account(user.clone()),
+        &DEPOSIT_FEE,
+    );
+    s.token
+        .with_source_account(&user)
+        .approve(&Signature::Invoker, &0, &s.contract_id, &(2 * DEPOSIT_FEE));
+
+    deposit(&s, &user);
+    deposit(&s, &user);
+
+    assert_eq!(s.token.balance(&s.contract_id), 2 * DEPOSIT_FEE);
+    assert_eq!(s.client.export_state(&0).get_unchecked(0).unwrap().amount, 2 * DEPOSIT_FEE);
+    assert_eq!(
+        s.client.preview_cancellation(&0).get_unchecked(0).unwrap().refund,
+        2 * DEPOSIT_FEE
+    );
+    assert!(s.client.preview_cancellation(&1).is_empty());
+    assert!(s.client.preview_cancellation(&u32::MAX).is_empty());
+}
+
+/*
+// Auxiliary contracts for the attack tests below. They only exist in test builds.
+// - ReentrantToken: a payout token whose xfer calls back into the meetup contract
+// - ClaimForwarder: a contract that calls distribute or relays claim_for as a sub-invocation
+// - ContractDepositor: a contract that registers and claims for itself
+// - SavingsAccount: a savings contract that records what it was credited
+// - DriftingOracle: a price feed whose quotes drifted 0.5% up since the wallet quoted
+// - Registry: a personhood verifier that knows nobody
+*/
+pub struct ReentrantToken;
+
+#[contractimpl]
+impl ReentrantToken {
+    pub fn set_target(env: Env, target: BytesN<32>) {
+        env.storage().set(symbol!("target"), target);
+    }
+
+    pub fn decimals(_env: Env) -> u32 {
+        7
+    }
+
+    pub fn symbol(env: Env) -> Bytes {
+        Bytes::from_slice(&env, b"EVIL")
+    }
+
+    pub fn allowance(_env: Env, _from: Identifier, _spender: Identifier) -> i128 {
+        i128::MAX
+    }
+
+    pub fn balance(env: Env, _id: Identifier) -> i128 {
+        env.storage().get(symbol!("held")).unwrap_or(Ok(0)).unwrap()
+    }
+
+    pub fn xfer_from(
+        env: Env,
+        _sig: Signature,
+        _nonce: i128,
+        _from: Identifier,
+        _to: Identifier,
+        amount: i128,
+    ) {
+        let held: i128 = env.storage().get(symbol!("held")).unwrap_or(Ok(0)).unwrap();
+        env.storage().set(symbol!("held"), held + amount);
+    }
+
+    pub fn xfer(env: Env, _sig: Signature, _nonce: i128, _to: Identifier, amount: i128) {
+        let held: i128 = env.storage().get(symbol!("held")).unwrap_or(Ok(0)).unwrap();
+        env.storage().set(symbol!("held"), held - amount);
+
+        // try to claim again while the first payout is still in flight
+        let target: BytesN<32> = env.storage().get_unchecked(symbol!("target")).unwrap();
+        ProfitDistributionContractClient::new(&env, &target).distribute();
+    }
+}
+
+pub struct ClaimForwarder;
+
+#[contractimpl]
+impl ClaimForwarder {
+    pub fn forward(env: Env, target: BytesN<32>) {
+        ProfitDistributionContractClient::new(&env, &target).distribute();
+    }
+
+    pub fn relay(env: Env, target: BytesN<32>, attendee_sig: Signature, nonce: i128) {
+        ProfitDistributionContractClient::new(&env, &target).claim_for(&attendee_sig, &nonce);
+    }
+}
+
+pub struct ContractDepositor;
+
+#[contractimpl]
+impl ContractDepositor {
+    pub fn register(env: Env, meetup: BytesN<32>, token: BytesN<32>, amount: i128) {
+        let me = Identifier::Contract(env.get_current_contract());
+        token::Client::new(&env, &token).approve(
+            &Signature::Invoker,
+            &0,
+            &Identifier::Contract(meetup.clone()),
+            &amount,
+        );
+        ProfitDistributionContractClient::new(&env, &meetup).deposit(
+            &token,
+            &amount,
+            &vec![&env, me],
+            &TimeBound {
+                kind: TimeBoundKind::Before,
+                timestamp: env.ledger().timestamp() + 86400,
+            },
+            &None,
+            &None,
+            &0,
+            &None,
+            &None,
+        );
+    }
+
+    pub fn claim(env: Env, meetup: BytesN<32>) {
+        ProfitDistributionContractClient::new(&env, &meetup).distribute();
+    }
+}
+
+pub struct SavingsAccount;
+
+#[contractimpl]
+impl SavingsAccount {
+    pub fn save(env: Env, owner: Identifier, amount: i128) {
+        let saved: i128 = env.storage().get(owner.clone()).unwrap_or(Ok(0)).unwrap();
+        env.storage().set(owner, saved + amount);
+    }
+
+    pub fn saved(env: Env, owner: Identifier) -> i128 {
+        env.storage().get(owner).unwrap_or(Ok(0)).unwrap()
+    }
+}
+
+pub struct DriftingOracle;
+
+#[contractimpl]
+impl DriftingOracle {
+    pub fn quote(_env: Env, _token: BytesN<32>, usd_cents: i128) -> i128 {
+        usd_cents * 100_000 * 1005 / 1000
+    }
+}
+
+#[test]
+fn test_usd_fee_accepts_deposits_within_the_slippage_band() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+    let oracle = env.register_contract(None, DriftingOracle);
+
+    s.client.with_source_account(&s.admin).set_usd_fee(&UsdFee {
+        oracle,
+        usd_cents: DEPOSIT_FEE / 100_000,
+        slippage_bps: 100,
+    });
+    // the wallet quoted DEPOSIT_FEE before the price moved
+    deposit(&s, &user);
+    let receipt = s.client.deposit_proof(&Identifier::Account(user));
+    assert_eq!(receipt.registration.amount, DEPOSIT_FEE);
+}
+
+pub struct Registry;
+
+#[contractimpl]
+impl Registry {
+    pub fn is_human(_env: Env, _addr: Identifier, _policy: BytesN<32>) -> bool {
+        false
+    }
+}
+
+#[test]
+#[should_panic(expected = "Status(ContractError(16))")]
+fn test_personhood_gate_refuses_unverified_registrants() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+    let verifier = env.register_contract(None, Registry);
+
+    s.client
+        .with_source_account(&s.admin)
+        .set_personhood_gate(&Some(PersonhoodGate {
+            verifier,
+            policy: BytesN::from_array(&env, &[1; 32]),
+        }));
+    assert_eq!(
+        s.client.permissions_of(&Identifier::Account(user.clone())),
+        0
+    );
+    deposit(&s, &user);
+}
+
+#[test]
+fn test_auto_save_routes_part_of_the_payout() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+    let user_id = Identifier::Account(user.clone());
+    let savings = env.register_contract(None, SavingsAccount);
+
+    deposit(&s, &user);
+    s.client
+        .with_source_account(&s.admin)
+        .check_in_many(&vec![&env, user_id.clone()]);
+    s.client.with_source_account(&user).set_auto_save(&2500, &savings);
+    s.client.with_source_account(&user).claim();
+
+    let saved = DEPOSIT_FEE / 4;
+    assert_eq!(SavingsAccountClient::new(&env, &savings).saved(&user_id), saved);
+    assert_eq!(s.token.balance(&Identifier::Contract(savings)), saved);
+    assert_eq!(s.token.balance(&user_id), DEPOSIT_FEE - saved);
+}
+
+#[test]
+fn test_claim_with_donation_splits_the_share() {
+    let env = Env::default();
+    let s = setup_with(
+        &env,
+        Init {
+            features: FEATURE_DONATIONS,
+            ..default_init(&env)
+        },
+    );
+    let user = funded_user(&s);
+    let user_id = Identifier::Account(user.clone());
+    let charity = Identifier::Account(env.accounts().generate());
+
+    s.client.with_source_account(&s.admin).set_charity(&charity);
+    deposit(&s, &user);
+    s.client
+        .with_source_account(&s.admin)
+        .check_in_many(&vec![&env, user_id.clone()]);
+
+    s.client
+        .with_source_account(&user)
+        .claim_with_donation(&1000);
+    assert_eq!(s.token.balance(&charity), DEPOSIT_FEE / 10);
+    assert_eq!(s.token.balance(&user_id), DEPOSIT_FEE * 9 / 10);
+}
+
+#[test]
+#[should_panic(expected = "Status(ContractError(5))")]
+fn test_claim_with_donation_requires_the_feature() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+
+    deposit(&s, &user);
+    s.client
+        .with_source_account(&s.admin)
+        .check_in_many(&vec![&env, Identifier::Account(user.clone())]);
+    s.client
+        .with_source_account(&user)
+        .claim_with_donation(&1000);
+}
+
+#[test]
+fn test_simulated_claim_matches_the_real_one() {
+    let env = Env::default();
+    let s = setup_with(
+        &env,
+        Init {
+            features: FEATURE_DONATIONS,
+            ..default_init(&env)
+        },
+    );
+    let user = funded_user(&s);
+    let user_id = Identifier::Account(user.clone());
+    let charity = Identifier::Account(env.accounts().generate());
+    let savings = env.register_contract(None, SavingsAccount);
+
+    s.client.with_source_account(&s.admin).set_charity(&charity);
+    deposit(&s, &user);
+    s.client
+        .with_source_account(&s.admin)
+        .check_in_many(&vec![&env, user_id.clone()]);
+    s.client.with_source_account(&user).set_auto_save(&2500, &savings);
+
+    let preview = s.client.simulate_claim(&user_id, &1000);
+    assert!(preview.claimable);
+    s.client.with_source_account(&user).claim_with_donation(&1000);
+
+    assert_eq!(s.token.balance(&charity), preview.donation);
+    assert_eq!(s.token.balance(&Identifier::Contract(savings)), preview.saved);
+    assert_eq!(s.token.balance(&user_id), preview.payout);
+    assert_eq!(preview.donation + preview.saved + preview.payout, preview.share);
+}
+
+#[test]
+fn test_simulated_dust_claim_pays_nothing() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+    let user_id = Identifier::Account(user.clone());
+
+    s.client
+        .with_source_account(&s.admin)
+        .set_dust_threshold(&(DEPOSIT_FEE + 1));
+    deposit(&s, &user);
+    s.client
+        .with_source_account(&s.admin)
+        .check_in_many(&vec![&env, user_id.clone()]);
+
+    let preview = s.client.simulate_claim(&user_id, &0);
+    assert_eq!(preview.share, DEPOSIT_FEE);
+    assert_eq!(preview.payout, 0);
+    s.client.with_source_account(&user).claim();
+    assert_eq!(s.token.balance(&user_id), 0);
+}
+
+#[test]
+fn test_auto_save_falls_back_to_direct_payout() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+    let user_id = Identifier::Account(user.clone());
+
+    deposit(&s, &user);
+    s.client
+        .with_source_account(&s.admin)
+        .check_in_many(&vec![&env, user_id.clone()]);
+    // nothing is deployed there, so the savings call fails
+    s.client
+        .with_source_account(&user)
+        .set_auto_save(&2500, &BytesN::from_array(&env, &[7; 32]));
+    s.client.with_source_account(&user).claim();
+
+    assert_eq!(s.token.balance(&user_id), DEPOSIT_FEE);
+}
+
+#[cfg(feature = "client")]
+#[test]
+fn test_client_maps_contract_errors() {
+    use super::{client, Error};
+    use soroban_sdk::xdr::{HostFunction, ScStatus};
+
+    let env = Env::default();
+    let contract = BytesN::from_array(&env, &[1; 32]);
+    match client::invoke(&env, &contract, symbol!("rate"), (4u32,)) {
+        HostFunction::InvokeContract(call) => assert_eq!(call.len(), 3),
+        _ => panic!("not an invocation"),
+    }
+
+    assert_eq!(
+        client::contract_error(&ScStatus::ContractError(15)),
+        Some(Error::OutflowCapReached)
+    );
+    assert_eq!(client::contract_error(&ScStatus::Ok), None);
+}
+
+#[test]
+fn test_contract_depositor_is_credited_and_paid() {
+    let env = Env::default();
+    let s = setup(&env);
+    let depositor = env.register_contract(None, ContractDepositor);
+    let depositor_id = Identifier::Contract(depositor.clone());
+    let client = ContractDepositorClient::new(&env, &depositor);
+    let admin = s.client.with_source_account(&s.admin);
+
+    s.token
+        .with_source_account(&s.admin)
+        .mint(&Signature::Invoker, &0, &depositor_id, &DEPOSIT_FEE);
+    client.register(&s.contract, &s.token_id, &DEPOSIT_FEE);
+    assert_eq!(s.client.deposit_proof(&depositor_id).registration.amount, DEPOSIT_FEE);
+    assert_eq!(s.token.balance(&depositor_id), 0);
+
+    admin.check_in_many(&vec![&env, depositor_id.clone()]);
+    // a contract claiming its own share needs no opt-in
+    client.claim(&s.contract);
+    assert_eq!(s.token.balance(&depositor_id), DEPOSIT_FEE);
+}
+
+#[test]
+#[should_panic(expected = "payouts can't be requested by another contract")]
+fn test_sub_invocation_claims_are_refused() {
+    let env = Env::default();
+    let s = setup(&env);
+    let (attendee_id, signer) = signing_attendee(&s);
+    let forwarder = env.register_contract(None, ClaimForwarder);
+
+    let sig = sign(
+        &env,
+        &signer,
+        &s.contract,
+        symbol!("claim_for"),
+        (&attendee_id, 0i128),
+    );
+    // a contract relaying somebody else's claim needs the opt-in
+    ClaimForwarderClient::new(&env, &forwarder).relay(&s.contract, &sig, &0);
+}
+
+#[test]
+#[should_panic(expected = "not eligible to receive any deposit back")]
+fn test_sub_invocation_claims_as_the_calling_contract() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+    let forwarder = env.register_contract(None, ClaimForwarder);
+
+    deposit(&s, &user);
+    s.client
+        .with_source_account(&s.admin)
+        .check_in_many(&vec![&env, Identifier::Account(user.clone())]);
+
+    // the meetup contract sees the forwarder as its invoker, not the user who
+    // signed the transaction, so the user's share can't be claimed this way
+    ClaimForwarderClient::new(&env, &forwarder)
+        .with_source_account(&user)
+        .forward(&s.contract);
+}
+
+#[test]
+fn test_reentrant_token_cannot_claim_twice() {
+    let env = Env::default();
+    let admin = env.accounts().generate();
+    let user = env.accounts().generate();
+    let token_id = env.register_contract(None, ReentrantToken);
+    let contract = env.register_contract(None, ProfitDistributionContract);
+    let client = ProfitDistributionContractClient::new(&env, &contract);
+    ReentrantTokenClient::new(&env, &token_id).set_target(&contract);
+
+    initialize(&env, &client, &admin, &token_id, default_init(&env));
+    client.with_source_account(&user).deposit(
+        &token_id,
+        &DEPOSIT_FEE,
+        &vec![&env, Identifier::Account(user.clone())],
+        &TimeBound {
+            kind: TimeBoundKind::Before,
+            timestamp: env.ledger().timestamp() + 86400,
+        },
+        &None,
+        &None,
+        &0,
+        &None,
+        &None,
+    );
+    client
+        .with_source_account(&admin)
+        .check_in_many(&vec![&env, Identifier::Account(user.clone())]);
+
+    // The callback is rejected (the host doesn't allow re-entry, and the inner
+    // call's invoker would be the token anyway), which reverts the whole claim
+    assert!(client.with_source_account(&user).try_distribute().is_err());
+    assert!(!client.export_state(&0).get_unchecked(0).unwrap().claimed);
+    assert_eq!(client.events_count(), 0);
+}
+
+/*
+// Model-based test: random sequences of deposits, check-ins, claims and ledger
+// time jumps are applied both to the contract and to a small reference model,
+// and after every step the two have to agree on balances and on who may still
+// deposit or claim. The model only issues calls it expects to succeed, so any
+// panic is a divergence too. A fixed-seed xorshift keeps failures reproducible.
+*/
+const MODEL_USERS: usize = 4;
+
+struct Rng(u64);
+
+impl Rng {
+    fn below(&mut self, n: u64) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 % n
+    }
+}
+
+#[derive(Default)]
+struct Model {
+    now: u64,
+    registered: [bool; MODEL_USERS],
+    checked_in: [bool; MODEL_USERS],
+    claimed: [bool; MODEL_USERS],
+    balances: [i128; MODEL_USERS],
+    held: i128,
+    // every attendee's slice, fixed by the first claim
+    share: Option<i128>,
+}
+
+impl Model {
+    // the fixture's meetup is at ledger time 0
+    fn registration_open(&self) -> bool {
+        self.now == 0 && self.share.is_none()
+    }
+
+    // the fixture's fee is fixed, so everyone deposits once
+    fn can_deposit(&self, i: usize) -> bool {
+        self.registration_open() && !self.registered[i]
+    }
+
+    fn can_claim(&self, i: usize) -> bool {
+        self.now > 0 && self.checked_in[i] && !self.claimed[i]
+    }
+
+    fn settle(&mut self) -> i128 {
+        let pool = DEPOSIT_FEE * self.registered.iter().filter(|r| **r).count() as i128;
+        let attendees = self.checked_in.iter().filter(|c| **c).count() as i128;
+        *self.share.get_or_insert(pool / attendees)
+    }
+}
+
+fn run_model(seed: u64, steps: u32) {
+    let env = Env::default();
+    let s = setup(&env);
+    let mut rng = Rng(seed);
+    let mut model = Model {
+        balances: [DEPOSIT_FEE; MODEL_USERS],
+        ..Default::default()
+    };
+    let users: [AccountId; MODEL_USERS] = [
+        funded_user(&s),
+        funded_user(&s),
+        funded_user(&s),
+        funded_user(&s),
+    ];
+
+    for _ in 0..steps {
+        let i = rng.below(MODEL_USERS as u64) as usize;
+        let user_id = Identifier::Account(users[i].clone());
+        match rng.below(4) {
+            0 if model.can_deposit(i) => {
+                deposit(&s, &users[i]);
+                model.registered[i] = true;
+                model.balances[i] -= DEPOSIT_FEE;
+                model.held += DEPOSIT_FEE;
+            }
+            1 if model.registered[i] && model.share.is_none() => {
+                s.client
+                    .with_source_account(&s.admin)
+                    .check_in_many(&vec![&env, user_id]);
+                model.checked_in[i] = true;
+            }
+            2 => {
+                let jump = [0, 3600, 86400][rng.below(3) as usize];
+                env.ledger().with_mut(|ledger| ledger.timestamp += jump);
+                model.now += jump;
+            }
+            3 if model.can_claim(i) => {
+                s.client.with_source_account(&users[i]).distribute();
+                let share = model.settle();
+                model.claimed[i] = true;
+                model.balances[i] += share;
+                model.held -= share;
+            }
+            _ => {}
+        }
+
+        assert_eq!(s.token.balance(&s.contract_id), model.held);
+        for (i, user) in users.iter().enumerate() {
+            let user_id = Identifier::Account(user.clone());
+            let actions = s.client.permissions_of(&user_id);
+            assert_eq!(s.token.balance(&user_id), model.balances[i]);
+            assert_eq!(actions & ACTION_DEPOSIT != 0, model.can_deposit(i));
+            assert_eq!(actions & ACTION_DISTRIBUTE != 0, model.can_claim(i));
+        }
+    }
+}
+
+#[test]
+fn test_contract_matches_reference_model() {
+    for seed in [1, 7, 42, 1234, 99991] {
+        run_model(seed, 60);
+    }
+}
+
+// The engine is plain integer math, so these run without an Env
+
+#[test]
+fn test_engine_time_weight_decays_to_one() {
+    // 2x at contract start, 1.5x halfway, 1x at the deadline and after
+    assert_eq!(engine::time_weight(100, 0, 0, 1000), 200);
+    assert_eq!(engine::time_weight(100, 500, 0, 1000), 150);
+    assert_eq!(engine::time_weight(100, 1000, 0, 1000), 100);
+    assert_eq!(engine::time_weight(100, 2000, 0, 1000), 100);
+}
+
+#[test]
+fn test_engine_shares_never_exceed_payout() {
+    let weights = [3, 5, 7];
+    let total: i128 = weights.iter().sum();
+    let paid: i128 = weights.iter().map(|w| engine::share(1000, *w, total)).sum();
+
+    assert!(paid <= 1000);
+    assert_eq!(engine::share(1000, 1, 0), 0);
+}
+
+#[test]
+fn test_engine_reserve_absorbs_shortfall() {
+    assert_eq!(engine::pool_after_reserve(1000, 100, 1200), (900, 100));
+    assert_eq!(engine::pool_after_reserve(1000, 100, 950), (900, 50));
+    assert_eq!(engine::pool_after_reserve(1000, 100, 800), (800, 0));
+}
+
+#[test]
+fn test_engine_emergency_quorum() {
+    assert!(!engine::emergency_quorum_reached(749, 1000, 7500));
+    assert!(engine::emergency_quorum_reached(750, 1000, 7500));
+    assert_eq!(engine::emergency_share(600, 250, 1000), 150);
+}
+
+#[test]
+fn test_engine_phase_transitions() {
+    assert_eq!(engine::phase(10, 20, false, false, false, false), Phase::Registration);
+    assert_eq!(engine::phase(30, 20, false, false, false, false), Phase::AwaitingSettlement);
+    assert_eq!(engine::phase(30, 20, true, false, false, false), Phase::Settled);
+    assert_eq!(engine::phase(30, 20, true, true, false, false), Phase::Locked);
+    assert_eq!(engine::phase(30, 20, false, false, true, false), Phase::Emergency);
+    // falling short of the minimum pool only cancels once registration closed
+    assert_eq!(engine::phase(10, 20, false, false, false, true), Phase::Registration);
+    assert_eq!(engine::phase(30, 20, false, false, false, true), Phase::Cancelled);
+}
+
+#[test]
+fn test_engine_schedule_decays_after_grace() {
+    // 50 ledgers of grace, then 200 ledgers of decay
+    let late_penalty = [(50, 10000), (250, 0)];
+    assert_eq!(engine::schedule_bps(late_penalty, 0), 10000);
+    assert_eq!(engine::schedule_bps(late_penalty, 50), 10000);
+    assert_eq!(engine::schedule_bps(late_penalty, 51), 9950);
+    assert_eq!(engine::schedule_bps(late_penalty, 150), 5000);
+    assert_eq!(engine::schedule_bps(late_penalty, 249), 50);
+    assert_eq!(engine::schedule_bps(late_penalty, 250), 0);
+    assert_eq!(engine::schedule_bps(late_penalty, u64::MAX), 0);
+    assert_eq!(engine::bps_of(1000, engine::schedule_bps(late_penalty, 51)), 995);
+}
+
+#[test]
+fn test_engine_schedule_interpolates_each_segment() {
+    let tiers = [(10, 2000), (20, 6000), (40, 6000), (50, 10000)];
+    assert_eq!(engine::schedule_bps(tiers, 5), 2000);
+    assert_eq!(engine::schedule_bps(tiers, 15), 4000);
+    assert_eq!(engine::schedule_bps(tiers, 30), 6000);
+    assert_eq!(engine::schedule_bps(tiers, 45), 8000);
+    assert_eq!(engine::schedule_bps(tiers, 60), 10000);
+    assert_eq!(engine::schedule_bps([], 60), 10000);
+
+    assert!(engine::is_valid_schedule(tiers));
+    assert!(!engine::is_valid_schedule([]));
+    assert!(!engine::is_valid_schedule([(10, 2000), (10, 6000)]));
+    assert!(!engine::is_valid_schedule([(10, 10001)]));
+}
+
+#[test]
+fn test_engine_average_rating_in_hundredths() {
+    assert_eq!(engine::average_rating(0, 0), 0);
+    assert_eq!(engine::average_rating(5, 1), 500);
+    assert_eq!(engine::average_rating(11, 3), 366);
+}
+
+#[test]
+fn test_engine_slippage_band_is_symmetric() {
+    assert!(engine::within_slippage(1000, 1000, 0));
+    assert!(!engine::within_slippage(1001, 1000, 0));
+    assert!(engine::within_slippage(990, 1000, 100));
+    assert!(engine::within_slippage(1010, 1000, 100));
+    assert!(!engine::within_slippage(1011, 1000, 100));
+}
+
+#[test]
+fn test_late_penalty_decays_per_ledger() {
+    let env = Env::default();
+    let cohost = env.accounts().generate();
+    let mut splits = vec![&env];
+    splits.push_back(FeeSplit {
+        recipient: Identifier::Account(cohost),
+        bps: 10000,
+    });
+    let s = setup_with(
+        &env,
+        Init {
+            organizer_fee: OrganizerFee { bps: 1000, splits },
+            ..default_init(&env)
+        },
+    );
+    let user = funded_user(&s);
+    let user_id = Identifier::Account(user.clone());
+
+    // 10 ledgers of grace, then the fee decays to zero over the next 10
+    s.client
+        .with_source_account(&s.admin)
+        .set_late_penalty(&PiecewiseSchedule {
+            points: vec![
+                &env,
+                Breakpoint { at: 10, bps: 10000 },
+                Breakpoint { at: 20, bps: 0 },
+            ],
+        });
+    deposit(&s, &user);
+    s.client
+        .with_source_account(&s.admin)
+        .check_in_many(&vec![&env, user_id.clone()]);
+    let fee = DEPOSIT_FEE / 10;
+
+    // time alone doesn't count, only ledgers do
+    env.ledger().with_mut(|ledger| ledger.timestamp += 86400);
+    assert_eq!(s.client.simulate_claim(&user_id, &0).organizer_fee, fee);
+
+    env.ledger().with_mut(|ledger| ledger.sequence_number += 10);
+    assert_eq!(s.client.simulate_claim(&user_id, &0).organizer_fee, fee);
+
+    env.ledger().with_mut(|ledger| ledger.sequence_number += 1);
+    assert_eq!(
+        s.client.simulate_claim(&user_id, &0).organizer_fee,
+        fee * 9 / 10
+    );
+
+    env.ledger().with_mut(|ledger| ledger.sequence_number += 4);
+    assert_eq!(s.client.simulate_claim(&user_id, &0).organizer_fee, fee / 2);
+
+    env.ledger().with_mut(|ledger| ledger.sequence_number += 5);
+    assert_eq!(s.client.simulate_claim(&user_id, &0).organizer_fee, 0);
+}
+
+#[test]
+fn test_organizer_fee_vests_linearly() {
+    let env = Env::default();
+    let cohost = env.accounts().generate();
+    let cohost_id = Identifier::Account(cohost.clone());
+    let mut splits = vec![&env];
+    splits.push_back(FeeSplit {
+        recipient: cohost_id.clone(),
+        bps: 10000,
+    });
+    let s = setup_with(
+        &env,
+        Init {
+            organizer_fee: OrganizerFee { bps: 1000, splits },
+            ..default_init(&env)
+        },
+    );
+    let user = funded_user(&s);
+    let admin = s.client.with_source_account(&s.admin);
+
+    admin.set_fee_vesting(&1000);
+    deposit(&s, &user);
+    admin.check_in_many(&vec![&env, Identifier::Account(user.clone())]);
+    s.client.with_source_account(&user).distribute();
+    let fee = DEPOSIT_FEE / 10;
+
+    // nothing has vested at the meetup
+    assert!(admin.try_claim_organizer_fee().is_err());
+    assert_eq!(s.token.balance(&cohost_id), 0);
+
+    env.ledger().with_mut(|ledger| ledger.timestamp += 500);
+    assert_eq!(admin.claim_organizer_fee(), fee / 2);
+    assert_eq!(s.token.balance(&cohost_id), fee / 2);
+
+    // past the period only the rest is paid, and only once
+    env.ledger().with_mut(|ledger| ledger.timestamp += 1000);
+    assert_eq!(admin.claim_organizer_fee(), fee / 2);
+    assert_eq!(s.token.balance(&cohost_id), fee);
+    assert!(admin.try_claim_organizer_fee().is_err());
+}
+
+#[test]
+#[should_panic(expected = "this attendee already rated the event")]
+fn test_rating_is_one_per_attendee() {
+    let env = Env::default();
+    let s = setup(&env);
+    let user = funded_user(&s);
+
+    s.client
+        .with_source_account(&s.admin)
+        .set_rating_fee(&PiecewiseSchedule {
+            points: vec![
+                &env,
+                Breakpoint { at: 100, bps: 5000 },
+                Breakpoint { at: 500, bps: 10000 },
+            ],
+        });
+    deposit(&s, &user);
+    s.client
+        .with_source_account(&s.admin)
+        .check_in_many(&vec![&env, Identifier::Account(user.clone())]);
+    env.ledger().with_mut(|ledger| ledger.timestamp += 1);
+
+    s.client.with_source_account(&user).rate(&4);
+    assert_eq!(s.client.rating_tally().sum, 4);
+    s.client.with_source_account(&user).rate(&5);
+}
+
+#[test]
+fn test_engine_quadratic_match_favours_broad_support() {
+    assert_eq!(engine::isqrt(0), 0);
+    assert_eq!(engine::isqrt(2), 1);
+    assert_eq!(engine::isqrt(99), 9);
+    assert_eq!(engine::isqrt(100), 10);
+
+    // four backers of 25 against one backer of 100: same amount raised, but
+    // sqrt sums of 20 and 10, so the match splits 400:100
+    let broad = 4 * engine::isqrt(25);
+    let narrow = engine::isqrt(100);
+    let total_squares = broad * broad + narrow * narrow;
+    assert_eq!(engine::quadratic_match(1000, broad, total_squares), 800);
+    assert_eq!(engine::quadratic_match(1000, narrow, total_squares), 200);
+}
+
+/*
+// Golden interface: spec/interface.txt lists every entry of the contract spec,
+// the entrypoints along with the types they use, as clients and generated
+// bindings see them. The entries come from the spec consts the contract macros
+// generate, the same bytes that end up in the wasm's contractspecv0 section.
+// The test fails when the spec no longer matches, so a refactor can't break
+// clients silently. After an intended interface change, regenerate the file
+// with UPDATE_SPEC=1 cargo test and commit it with the change. New entrypoints
+// and contract types have to be listed here as well.
+*/
+fn exported_spec() -> std::vec::Vec<ScSpecEntry> {
+    let entries: std::vec::Vec<std::vec::Vec<u8>> = std::vec![
+        ProfitDistributionContract::spec_xdr_initialize().to_vec(),
+        ProfitDistributionContract::spec_xdr_deposit().to_vec(),
+        ProfitDistributionContract::spec_xdr_set_priority_surcharge().to_vec(),
+        ProfitDistributionContract::spec_xdr_buy_priority().to_vec(),
+        ProfitDistributionContract::spec_xdr_deposit_with_extra().to_vec(),
+        ProfitDistributionContract::spec_xdr_deposit_batch().to_vec(),
+        ProfitDistributionContract::spec_xdr_set_payments_router().to_vec(),
+        ProfitDistributionContract::spec_xdr_on_deposit().to_vec(),
+        ProfitDistributionContract::spec_xdr_simulate_deposit().to_vec(),
+        ProfitDistributionContract::spec_xdr_simulate_claim().to_vec(),
+        ProfitDistributionContract::spec_xdr_distribute().to_vec(),
+        ProfitDistributionContract::spec_xdr_claim().to_vec(),
+        ProfitDistributionContract::spec_xdr_push_payouts().to_vec(),
+        ProfitDistributionContract::spec_xdr_claim_with_donation().to_vec(),
+        ProfitDistributionContract::spec_xdr_claim_for().to_vec(),
+        ProfitDistributionContract::spec_xdr_set_auto_save().to_vec(),
+        ProfitDistributionContract::spec_xdr_approve_operator().to_vec(),
+        ProfitDistributionContract::spec_xdr_revoke_operator().to_vec(),
+        ProfitDistributionContract::spec_xdr_claim_as_operator().to_vec(),
+        ProfitDistributionContract::spec_xdr_nonce().to_vec(),
+        ProfitDistributionContract::spec_xdr_my_claim_opens_at().to_vec(),
+        ProfitDistributionContract::spec_xdr_block().to_vec(),
+        ProfitDistributionContract::spec_xdr_unblock().to_vec(),
+        ProfitDistributionContract::spec_xdr_is_blocked().to_vec(),
+        ProfitDistributionContract::spec_xdr_export_state().to_vec(),
+        ProfitDistributionContract::spec_xdr_add_staff().to_vec(),
+        ProfitDistributionContract::spec_xdr_remove_staff().to_vec(),
+        ProfitDistributionContract::spec_xdr_add_session_key().to_vec(),
+        ProfitDistributionContract::spec_xdr_revoke_session_key().to_vec(),
+        ProfitDistributionContract::spec_xdr_check_in_many().to_vec(),
+        ProfitDistributionContract::spec_xdr_set_rsvp_capacity().to_vec(),
+        ProfitDistributionContract::spec_xdr_rsvp().to_vec(),
+        ProfitDistributionContract::spec_xdr_rsvp_count().to_vec(),
+        ProfitDistributionContract::spec_xdr_set_sweep_destinations().to_vec(),
+        ProfitDistributionContract::spec_xdr_sweep_destinations().to_vec(),
+        ProfitDistributionContract::spec_xdr_set_allow_contract_callers().to_vec(),
+        ProfitDistributionContract::spec_xdr_set_strict_accounting().to_vec(),
+        ProfitDistributionContract::spec_xdr_reconcile().to_vec(),
+        ProfitDistributionContract::spec_xdr_lost_and_found().to_vec(),
+        ProfitDistributionContract::spec_xdr_shortfall().to_vec(),
+        ProfitDistributionContract::spec_xdr_return_lost().to_vec(),
+        ProfitDistributionContract::spec_xdr_set_attendance_oracle().to_vec(),
+        ProfitDistributionContract::spec_xdr_finalize_attendance().to_vec(),
+        ProfitDistributionContract::spec_xdr_grant_exception().to_vec(),
+        ProfitDistributionContract::spec_xdr_pending_check_in().to_vec(),
+        ProfitDistributionContract::spec_xdr_postpone().to_vec(),
+        ProfitDistributionContract::spec_xdr_set_cancel_penalty().to_vec(),
+        ProfitDistributionContract::spec_xdr_cancel_registration().to_vec(),
+        ProfitDistributionContract::spec_xdr_transfer_registration().to_vec(),
+        ProfitDistributionContract::spec_xdr_set_min_pool().to_vec(),
+        ProfitDistributionContract::spec_xdr_set_dust_threshold().to_vec(),
+        ProfitDistributionContract::spec_xdr_dust_of().to_vec(),
+        ProfitDistributionContract::spec_xdr_set_fee_vesting().to_vec(),
+        ProfitDistributionContract::spec_xdr_claim_organizer_fee().to_vec(),
+        ProfitDistributionContract::spec_xdr_set_late_penalty().to_vec(),
+        ProfitDistributionContract::spec_xdr_set_outflow_cap().to_vec(),
+        ProfitDistributionContract::spec_xdr_outflow_left().to_vec(),
+        ProfitDistributionContract::spec_xdr_set_personhood_gate().to_vec(),
+        ProfitDistributionContract::spec_xdr_set_usd_fee().to_vec(),
+        ProfitDistributionContract::spec_xdr_set_rating_fee().to_vec(),
+        ProfitDistributionContract::spec_xdr_set_check_in_weights().to_vec(),
+        ProfitDistributionContract::spec_xdr_rate().to_vec(),
+        ProfitDistributionContract::spec_xdr_rating_tally().to_vec(),
+        ProfitDistributionContract::spec_xdr_set_governance_drop().to_vec(),
+        ProfitDistributionContract::spec_xdr_set_auto_extension().to_vec(),
+        ProfitDistributionContract::spec_xdr_poke().to_vec(),
+        ProfitDistributionContract::spec_xdr_extend_registration().to_vec(),
+        ProfitDistributionContract::spec_xdr_refund().to_vec(),
+        ProfitDistributionContract::spec_xdr_preview_cancellation().to_vec(),
+        ProfitDistributionContract::spec_xdr_original_meetup_date().to_vec(),
+        ProfitDistributionContract::spec_xdr_lock_distribution().to_vec(),
+        ProfitDistributionContract::spec_xdr_execute_distribution().to_vec(),
+        ProfitDistributionContract::spec_xdr_vote_emergency().to_vec(),
+        ProfitDistributionContract::spec_xdr_emergency_withdraw().to_vec(),
+        ProfitDistributionContract::spec_xdr_verify_attendance_proof().to_vec(),
+        ProfitDistributionContract::spec_xdr_attendees_root().to_vec(),
+        ProfitDistributionContract::spec_xdr_payout_root().to_vec(),
+        ProfitDistributionContract::spec_xdr_verify_payout_proof().to_vec(),
+        ProfitDistributionContract::spec_xdr_past_event().to_vec(),
+        ProfitDistributionContract::spec_xdr_events_count().to_vec(),
+        ProfitDistributionContract::spec_xdr_registered().to_vec(),
+        ProfitDistributionContract::spec_xdr_attended().to_vec(),
+        ProfitDistributionContract::spec_xdr_share_of().to_vec(),
+        ProfitDistributionContract::spec_xdr_deposit_proof().to_vec(),
+        ProfitDistributionContract::spec_xdr_claim_statuses().to_vec(),
+        ProfitDistributionContract::spec_xdr_remove_attendee().to_vec(),
+        ProfitDistributionContract::spec_xdr_resolve_appeal().to_vec(),
+        ProfitDistributionContract::spec_xdr_expire_appeal().to_vec(),
+        ProfitDistributionContract::spec_xdr_escrow().to_vec(),
+        ProfitDistributionContract::spec_xdr_seats_left().to_vec(),
+        ProfitDistributionContract::spec_xdr_get_info().to_vec(),
+        ProfitDistributionContract::spec_xdr_payout_token_info().to_vec(),
+        ProfitDistributionContract::spec_xdr_events_schema_version().to_vec(),
+        ProfitDistributionContract::spec_xdr_protocol_config().to_vec(),
+        ProfitDistributionContract::spec_xdr_features().to_vec(),
+        ProfitDistributionContract::spec_xdr_reserve().to_vec(),
+        ProfitDistributionContract::spec_xdr_set_charity().to_vec(),
+        ProfitDistributionContract::spec_xdr_donated().to_vec(),
+        ProfitDistributionContract::spec_xdr_add_project().to_vec(),
+        ProfitDistributionContract::spec_xdr_fund_matching().to_vec(),
+        ProfitDistributionContract::spec_xdr_contribute().to_vec(),
+        ProfitDistributionContract::spec_xdr_finalize_round().to_vec(),
+        ProfitDistributionContract::spec_xdr_projects().to_vec(),
+        ProfitDistributionContract::spec_xdr_matching_pool().to_vec(),
+        ProfitDistributionContract::spec_xdr_add_candidate().to_vec(),
+        ProfitDistributionContract::spec_xdr_vote().to_vec(),
+        ProfitDistributionContract::spec_xdr_finalize_votes().to_vec(),
+        ProfitDistributionContract::spec_xdr_set_metadata_cid().to_vec(),
+        ProfitDistributionContract::spec_xdr_metadata_cid().to_vec(),
+        ProfitDistributionContract::spec_xdr_announce().to_vec(),
+        ProfitDistributionContract::spec_xdr_set_rejection_reason().to_vec(),
+        ProfitDistributionContract::spec_xdr_latest_announcement().to_vec(),
+        ProfitDistributionContract::spec_xdr_set_expense_cap().to_vec(),
+        ProfitDistributionContract::spec_xdr_submit_expense().to_vec(),
+        ProfitDistributionContract::spec_xdr_approve_expense().to_vec(),
+        ProfitDistributionContract::spec_xdr_set_release_schedule().to_vec(),
+        ProfitDistributionContract::spec_xdr_release().to_vec(),
+        ProfitDistributionContract::spec_xdr_expenses().to_vec(),
+        ProfitDistributionContract::spec_xdr_answers_of().to_vec(),
+        ProfitDistributionContract::spec_xdr_issue_voucher().to_vec(),
+        ProfitDistributionContract::spec_xdr_mark_unused().to_vec(),
+        ProfitDistributionContract::spec_xdr_commit_voucher_claim().to_vec(),
+        ProfitDistributionContract::spec_xdr_claim_with_voucher().to_vec(),
+        ProfitDistributionContract::spec_xdr_time_until().to_vec(),
+        ProfitDistributionContract::spec_xdr_required_allowance().to_vec(),
+        ProfitDistributionContract::spec_xdr_permissions_of().to_vec(),
+        ProfitDistributionContract::spec_xdr_limits().to_vec(),
+        ProfitDistributionContract::spec_xdr_close().to_vec(),
+        ProfitDistributionContract::spec_xdr_candidates().to_vec(),
+        ProfitDistributionContract::spec_xdr_forfeited().to_vec(),
+        super::DataKey::spec_xdr().to_vec(),
+        super::TimeBoundKind::spec_xdr().to_vec(),
+        super::TimeBound::spec_xdr().to_vec(),
+        super::ClaimWindows::spec_xdr().to_vec(),
+        super::FeeSplit::spec_xdr().to_vec(),
+        super::ProtocolFee::spec_xdr().to_vec(),
+        super::OrganizerFee::spec_xdr().to_vec(),
+        super::Referrals::spec_xdr().to_vec(),
+        super::DepositLimits::spec_xdr().to_vec(),
+        super::ShareMode::spec_xdr().to_vec(),
+        super::PayoutMode::spec_xdr().to_vec(),
+        super::Registration::spec_xdr().to_vec(),
+        super::DepositReceipt::spec_xdr().to_vec(),
+        super::ExecAudit::spec_xdr().to_vec(),
+        super::PersonhoodGate::spec_xdr().to_vec(),
+        super::UsdFee::spec_xdr().to_vec(),
+        super::AutoSave::spec_xdr().to_vec(),
+        super::RatingTally::spec_xdr().to_vec(),
+        super::Outflow::spec_xdr().to_vec(),
+        super::RefundRow::spec_xdr().to_vec(),
+        super::GovernanceDrop::spec_xdr().to_vec(),
+        super::Breakpoint::spec_xdr().to_vec(),
+        super::PiecewiseSchedule::spec_xdr().to_vec(),
+        super::AutoExtension::spec_xdr().to_vec(),
+        super::ExceptionPolicy::spec_xdr().to_vec(),
+        super::Escrow::spec_xdr().to_vec(),
+        super::RegistrationRow::spec_xdr().to_vec(),
+        super::TokenInfo::spec_xdr().to_vec(),
+        super::EventInfo::spec_xdr().to_vec(),
+        super::EventStats::spec_xdr().to_vec(),
+        super::DepositPreview::spec_xdr().to_vec(),
+        super::ClaimPreview::spec_xdr().to_vec(),
+        super::RoundProject::spec_xdr().to_vec(),
+        super::ContributionKey::spec_xdr().to_vec(),
+        super::Candidate::spec_xdr().to_vec(),
+        super::Milestone::spec_xdr().to_vec(),
+        super::Expense::spec_xdr().to_vec(),
+        super::Checkpoint::spec_xdr().to_vec(),
+        super::Announcement::spec_xdr().to_vec(),
+        super::Limits::spec_xdr().to_vec(),
+        super::ClaimStatus::spec_xdr().to_vec(),
+        super::Rejection::spec_xdr().to_vec(),
+        super::DepositPermit::spec_xdr().to_vec(),
+        super::DepositBalance::spec_xdr().to_vec(),
+        super::Error::spec_xdr().to_vec(),
+    ];
+    entries.iter().map(|xdr| ScSpecEntry::from_xdr(xdr).unwrap()).collect()
+}
+
+fn spec_type(type_: &ScSpecTypeDef) -> String {
+    match type_ {
+        ScSpecTypeDef::Val => "RawVal".into(),
+        ScSpecTypeDef::U64 => "u64".into(),
+        ScSpecTypeDef::I64 => "i64".into(),
+        ScSpecTypeDef::U128 => "u128".into(),
+        ScSpecTypeDef::I128 => "i128".into(),
+        ScSpecTypeDef::U32 => "u32".into(),
+        ScSpecTypeDef::I32 => "i32".into(),
+        ScSpecTypeDef::Bool => "bool".into(),
+        ScSpecTypeDef::Symbol => "Symbol".into(),
+        ScSpecTypeDef::Bitset => "Bitset".into(),
+        ScSpecTypeDef::Status => "Status".into(),
+        ScSpecTypeDef::Bytes => "Bytes".into(),
+        ScSpecTypeDef::Invoker => "Invoker".into(),
+        ScSpecTypeDef::AccountId => "AccountId".into(),
+        ScSpecTypeDef::Option(option) => format!("Option<{}>", spec_type(&option.value_type)),
+        ScSpecTypeDef::Result(result) => format!(
+            "Result<{}, {}>",
+            spec_type(&result.ok_type),
+            spec_type(&result.error_type)
+        ),
+        ScSpecTypeDef::Vec(vec) => format!("Vec<{}>", spec_type(&vec.element_type)),
+        ScSpecTypeDef::Map(map) => format!(
+            "Map<{}, {}>",
+            spec_type(&map.key_type),
+            spec_type(&map.value_type)
+        ),
+        ScSpecTypeDef::Set(set) => format!("Set<{}>", spec_type(&set.element_type)),
+        ScSpecTypeDef::Tuple(tuple) => {
+            let types: std::vec::Vec<String> = tuple.value_types.iter().map(spec_type).collect();
+            format!("({})", types.join(", "))
+        }
+        ScSpecTypeDef::BytesN(bytes) => format!("BytesN<{}>", bytes.n),
+        ScSpecTypeDef::Udt(udt) => format!("{}", udt.name),
+    }
+}
+
+/*
+// One line of spec/interface.txt, e.g. fn name(arg: Type, ...) -> Output or
+// struct Name { field: Type, ... }
+*/
+fn spec_line(entry: &ScSpecEntry) -> String {
+    match entry {
+        ScSpecEntry::FunctionV0(function) => {
+            let inputs: std::vec::Vec<String> = function
+                .inputs
+                .iter()
+                .map(|input| format!("{}: {}", input.name, spec_type(&input.type_)))
+                .collect();
+            let mut line = format!("fn {}({})", function.name, inputs.join(", "));
+            if let Some(output) = function.outputs.first() {
+                line += &format!(" -> {}", spec_type(output));
+            }
+            line
+        }
+        ScSpecEntry::UdtStructV0(udt) => {
+            let fields: std::vec::Vec<String> = udt
+                .fields
+                .iter()
+                .map(|field| format!("{}: {}", field.name, spec_type(&field.type_)))
+                .collect();
+            format!("struct {} {{ {} }}", udt.name, fields.join(", "))
+        }
+        ScSpecEntry::UdtUnionV0(udt) => {
+            let cases: std::vec::Vec<String> = udt
+                .cases
+                .iter()
+                .map(|case| match &case.type_ {
+                    Some(type_) => format!("{}({})", case.name, spec_type(type_)),
+                    None => format!("{}", case.name),
+                })
+                .collect();
+            format!("union {} {{ {} }}", udt.name, cases.join(", "))
+        }
+        ScSpecEntry::UdtEnumV0(udt) => {
+            let cases: std::vec::Vec<String> = udt
+                .cases
+                .iter()
+                .map(|case| format!("{} = {}", case.name, case.value))
+                .collect();
+            format!("enum {} {{ {} }}", udt.name, cases.join(", "))
+        }
+        ScSpecEntry::UdtErrorEnumV0(udt) => {
+            let cases: std::vec::Vec<String> = udt
+                .cases
+                .iter()
+                .map(|case| format!("{} = {}", case.name, case.value))
+                .collect();
+            format!("error {} {{ {} }}", udt.name, cases.join(", "))
+        }
+    }
+}
+
+#[test]
+fn test_interface_matches_golden_spec() {
+    // Sorted, so the file doesn't depend on the order entries are listed in
+    let mut lines: std::vec::Vec<String> = exported_spec().iter().map(spec_line).collect();
+    lines.sort();
+    let current = lines.join("\n") + "\n";
+    if std::env::var("UPDATE_SPEC").is_ok() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/spec/interface.txt");
+        std::fs::write(path, &current).unwrap();
+        return;
+    }
+
+    let golden = include_str!("../spec/interface.txt");
+    for (expected, actual) in golden.lines().zip(current.lines()) {
+        assert_eq!(actual, expected, "the contract interface changed, see spec/interface.txt");
+    }
+    assert_eq!(current, golden, "the contract interface changed, see spec/interface.txt");
+}
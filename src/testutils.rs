@@ -0,0 +1,29 @@
+#![cfg(any(test, feature = "testutils"))]
+
+/*
+// Helpers for front-ends and contracts integrating with this one. The most common
+// integration failure is calling deposit without enough allowance, so the flow is:
+// read required_allowance, approve exactly that, then deposit.
+*/
+use crate::{token, ProfitDistributionContractClient};
+use soroban_auth::{Identifier, Signature};
+use soroban_sdk::{AccountId, BytesN, Env};
+
+// Approves what `user` needs to register with the meetup contract `contract` and
+// returns the approved amount
+pub fn approve_required_allowance(
+    env: &Env,
+    contract: &BytesN<32>,
+    token: &BytesN<32>,
+    user: &AccountId,
+) -> i128 {
+    let client = ProfitDistributionContractClient::new(env, contract);
+    let required = client.required_allowance(&Identifier::Account(user.clone()));
+    token::Client::new(env, token).with_source_account(user).approve(
+        &Signature::Invoker,
+        &0,
+        &Identifier::Contract(contract.clone()),
+        &required,
+    );
+    required
+}